@@ -1,14 +1,88 @@
 use clap::{crate_version, App, AppSettings, Arg, SubCommand};
 
 /// Generate cli argument parser without the `complete` subcommand.
-pub fn build_cli_nocomplete() -> App<'static, 'static> {
+///
+/// Subcommand abbreviations are left out of shell completions, to keep them short.
+pub fn build_cli_nocomplete(abbrev: bool) -> App<'static, 'static> {
+    let abbrevs = |name, extra| if abbrev { abbrevs(name, extra) } else { vec![] };
     let arg_limit =
         Arg::with_name("limit").long("limit")
                                .takes_value(true)
                                .default_value("10")
                                .help("Use the last N merge times to predict next merge time.");
-    let arg_pkg =
-        Arg::with_name("package").takes_value(true).help("Show only packages matching <package>.");
+    let arg_algo = Arg::with_name("predict-algo")
+        .long("predict-algo")
+        .value_name("mean,median,ewma,trimmed")
+        .possible_values(&["mean", "median", "ewma", "trimmed"])
+        .hide_possible_values(true)
+        .default_value("mean")
+        .help("Predict using the (mean), (median), (ewma) or (trimmed) mean of the last merge times.")
+        .long_help("Algorithm used to predict the next merge time from the last --limit ones:\n\
+* mean:    plain average.\n\
+* median:  middle value, ignores outliers.\n\
+* ewma:    exponentially weighted moving average, recent merges count more.\n\
+* trimmed: average without the fastest and slowest 10% (at least one each with 3 values or more).");
+    let arg_pkg = Arg::with_name("package")
+        .takes_value(true)
+        .multiple(true)
+        .help("Show only packages matching <package>.")
+        .long_help("Show only packages matching any <package>.\n\
+Can also be a dependency atom starting with an operator, like '>=sys-devel/gcc-12', '~gcc-12.2.0' or '=dev-lang/rust-1.7*', \
+to match on both name and version (see `man 5 ebuild`).\n\
+Can also be a package set like '@world', '@system', or the name of a file in --setsdir.");
+    let arg_exclude = Arg::with_name("exclude")
+        .short("x")
+        .long("exclude")
+        .value_name("package")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .help("Ignore packages matching <package>.")
+        .long_help("Ignore packages matching <package>. Can be used multiple times.\n\
+Syntax is the same as <package>, for example `-x '^virtual/' -x '/acct-'`.");
+    let arg_pkgfile = Arg::with_name("packagefile")
+        .long("package-file")
+        .value_name("file")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .help("Read <package> search terms from a file, one per line.")
+        .long_help("Read <package> search terms from a file, one per line. Can be used multiple times.\n\
+Empty lines and lines starting with '#' are ignored.");
+    let arg_mindur = Arg::with_name("min-duration")
+        .long("min-duration")
+        .value_name("duration")
+        .takes_value(true)
+        .help("Only consider events that took at least <duration>.")
+        .long_help("Only consider events that took at least <duration>.\n\
+Accepts the same formats as --duration outputs, like '1:30:00', '90:00' or '5400'. \
+Events with an unknown duration are ignored.");
+    let arg_maxdur = Arg::with_name("max-duration")
+        .long("max-duration")
+        .value_name("duration")
+        .takes_value(true)
+        .help("Only consider events that took at most <duration>.")
+        .long_help("Only consider events that took at most <duration>.\n\
+Accepts the same formats as --duration outputs, like '1:30:00', '90:00' or '5400'. \
+Events with an unknown duration are ignored.");
+    let arg_first = Arg::with_name("first")
+        .long("first")
+        .value_name("num")
+        .takes_value(true)
+        .conflicts_with("last")
+        .help("Only show the first <num> entries.")
+        .long_help("Only show the first <num> entries.\n\
+Entries are selected in chronological order, before --reverse is applied. Parsing stops as soon as enough entries were found.");
+    let arg_last = Arg::with_name("last")
+        .long("last")
+        .value_name("num")
+        .takes_value(true)
+        .help("Only show the last <num> entries.")
+        .long_help("Only show the last <num> entries.\n\
+Entries are selected in chronological order, before --reverse is applied. Use this instead of piping to `tail`, to keep colors. \
+The whole log is still parsed, so this isn't faster than --first on big logs.");
+    let arg_reverse =
+        Arg::with_name("reverse").short("r").long("reverse").help("Show newest entries first.");
     let arg_exact = Arg::with_name("exact")
         .short("e")
         .long("exact")
@@ -47,7 +121,6 @@ The grouping key is displayed in the first column. Weeks start on monday and are
         .global_setting(AppSettings::DeriveDisplayOrder)
         .global_setting(AppSettings::UnifiedHelpMessage)
         .setting(AppSettings::DisableHelpSubcommand)
-        .setting(AppSettings::InferSubcommands)
         .setting(AppSettings::SubcommandRequiredElseHelp)
         .setting(AppSettings::VersionlessSubcommands)
         .about("A fast, accurate, ergonomic EMerge LOg Parser.\nhttps://github.com/vincentdephily/emlop")
@@ -98,6 +171,41 @@ Accepts formats like '2018-03-04', '2018-03-04 12:34:56', '2018-03-04T12:34', '1
              .takes_value(true)
              .default_value("/var/log/emerge.log")
              .help("Location of emerge log file."))
+        .arg(Arg::with_name("worldfile")
+             .value_name("file")
+             .long("worldfile")
+             .global(true)
+             .takes_value(true)
+             .default_value("/var/lib/portage/world")
+             .help("Location of portage world file, used for @world and highlighting."))
+        .arg(Arg::with_name("setsdir")
+             .value_name("dir")
+             .long("setsdir")
+             .global(true)
+             .takes_value(true)
+             .default_value("/etc/portage/sets")
+             .help("Location of custom package sets."))
+        .arg(Arg::with_name("profile")
+             .value_name("dir")
+             .long("profile")
+             .global(true)
+             .takes_value(true)
+             .default_value("/etc/portage/make.profile")
+             .help("Location of portage profile, used for @system."))
+        .arg(Arg::with_name("pkgdir")
+             .value_name("dir")
+             .long("pkgdir")
+             .global(true)
+             .takes_value(true)
+             .default_value("/var/db/pkg")
+             .help("Location of installed package database (VDB)."))
+        .arg(Arg::with_name("timesfile")
+             .value_name("file")
+             .long("timesfile")
+             .global(true)
+             .takes_value(true)
+             .default_value("/var/lib/emlop/times.csv")
+             .help("Location of merge times imported from other machines, used by predict."))
         .arg(Arg::with_name("verbose")
              .short("v")
              .global(true)
@@ -113,39 +221,269 @@ Accepts formats like '2018-03-04', '2018-03-04 12:34:56', '2018-03-04T12:34', '1
              .value_name("when")
              .help("Enable color (auto/always/never/y/n)."))
         .subcommand(SubCommand::with_name("log")
+                    .aliases(&abbrevs("log", &[]))
                     .about("Show log of sucessful merges, unmerges and syncs.")
                     .long_about("Show log of sucessful merges, unmerges and syncs.\n\
 * (Un)merges: date, duration, package name-version.\n\
 * Syncs:      date, duration.")
                     .help_message("Show short (-h) or detailed (--help) help.")
                     .arg(&arg_show_l)
+                    .arg(Arg::with_name("compare")
+                         .short("c")
+                         .long("compare")
+                         .help("Mark merges as (N)ew, (U)pgrade, (D)owngrade, or (R)einstall, with the previous version.")
+                         .long_help("Mark merges as (N)ew, (U)pgrade, (D)owngrade, or (R)einstall, and show the replaced version in brackets.\n\
+Compares with the previous merge of the same package in the parsed log (taking --from into account), using Gentoo version rules."))
+                    .arg(Arg::with_name("time")
+                         .long("time")
+                         .value_name("start,end,both")
+                         .possible_values(&["start","end","both"])
+                         .hide_possible_values(true)
+                         .default_value("end")
+                         .help("Show the (start), (end), or (both) times of events.")
+                         .long_help("Show the (start), (end), or (both) times of events.\n\
+With 'both', the time range is displayed as 'start - end'. An unknown start time is displayed as '?'."))
+                    .arg(&arg_mindur)
+                    .arg(&arg_maxdur)
+                    .arg(&arg_first)
+                    .arg(&arg_last)
+                    .arg(&arg_reverse)
                     .arg(&arg_exact)
+                    .arg(&arg_exclude)
+                    .arg(&arg_pkgfile)
                     .arg(&arg_pkg))
         .subcommand(SubCommand::with_name("predict")
+                    .aliases(&abbrevs("predict", &[]))
                     .about("Predict merge time for current or pretended merges.")
                     .long_about("Predict merge time for current or pretended merges.\n\
-* If input is a terminal, predict time for the current merge (if any).\n\
-* If input is a pipe (for example by running `emerge -rOp|emlop p`), predict time for those merges.")
+* If input is a terminal, predict time for the current merge (if any), with its progress and finish time.\n\
+* If input is a pipe (for example by running `emerge -rOp|emlop p`), predict time for those merges.\n\
+Use `emerge -v` to get the package slots, so that predictions only use merges of the same slot.\n\
+Each prediction comes with a range of likely times (10th to 90th percentile of the last --limit merges), \
+which are added up for the whole list.\n\
+Packages that were never merged get an estimate based on similar packages, marked with a '~' (see --default-time).\n\
+If merges became clearly faster or slower at some point (like after a hardware upgrade), older merge times are \
+rescaled to the current speed (use -vv to see it).")
                     .help_message("Show short (-h) or detailed (--help) help.")
-                    .arg(&arg_limit))
+                    .arg(&arg_limit)
+                    .arg(&arg_algo)
+                    .arg(Arg::with_name("jobs")
+                         .long("jobs")
+                         .value_name("num")
+                         .takes_value(true)
+                         .default_value("1")
+                         .help("Estimate total time for <num> parallel merges (like `emerge --jobs`).")
+                         .long_help("Estimate total time for <num> parallel merges (like `emerge --jobs`).\n\
+Merges are scheduled in order, each one starting as soon as a job is free. Dependencies between merges are ignored, \
+so the estimate is optimistic with more than one job. See the `jobs` subcommand for how many merges usually run in parallel."))
+                    .arg(Arg::with_name("speed-factor")
+                         .long("speed-factor")
+                         .value_name("ratio")
+                         .takes_value(true)
+                         .help("Multiply imported merge times by <ratio>.")
+                         .long_help("Multiply merge times imported from another machine (see `import`) by <ratio>.\n\
+Use 0.5 if this machine merges twice as fast. Defaults to the median ratio between local and imported times, \
+for packages merged on both machines (or 1 if there are fewer than 5 such packages)."))
+                    .arg(Arg::with_name("default-time")
+                         .long("default-time")
+                         .value_name("duration")
+                         .takes_value(true)
+                         .help("Merge time of packages that can't be estimated otherwise.")
+                         .long_help("Merge time of packages that can't be estimated otherwise.\n\
+Packages that were never merged are estimated from their `-bin` variant (or the reverse), then from the median \
+of other packages in the same category, then using this duration. Estimates are marked with a '~'.\n\
+Accepts the same formats as --duration outputs, like '1:30:00', '90:00' or '5400'."))
+                    .arg(&arg_exact)
+                    .arg(&arg_exclude)
+                    .arg(&arg_pkgfile)
+                    .arg(&arg_pkg))
         .subcommand(SubCommand::with_name("stats")
+                    .aliases(&abbrevs("stats", &[]))
                     .about("Show statistics about sucessful merges, unmerges and syncs.")
                     .long_about("Show statistics about sucessful (un)merges (overall or per package) and syncs.\n\
 * <package>: merge count, total merge time, predicted merge time, unmerge count, total unmerge time, predicted unmerge time.\n\
+  Packages with more than one slot get one line per slot.\n\
 * Total:     merge count, total merge time, average merge time,   unmerge count, total unmerge time, average unmerge time.\n\
 * Sync:      sync count,  total sync time,  predicted sync time.")
                     .help_message("Show short (-h) or detailed (--help) help.")
                     .arg(&arg_show_s)
                     .arg(&arg_group)
+                    .arg(Arg::with_name("details")
+                         .long("details")
+                         .conflicts_with_all(&["group", "versions"])
+                         .help("Show details about installed versions, from the package database.")
+                         .long_help("Show merge stats of each package, with details about each installed version from the package database (see --pkgdir):\n\
+SLOT, repository, BUILD_TIME, SIZE, CHOST, CFLAGS, and USE flags."))
+                    .arg(Arg::with_name("versions")
+                         .long("versions")
+                         .help("Show per-version instead of per-package merge stats.")
+                         .long_help("Show per-version instead of per-package merge stats.\n\
+* <package>-<version>: merge count, total merge time, average merge time, change from previous version.\n\
+Versions are sorted according to Gentoo rules (1.9 < 1.10 < 1.10-r1 < 1.11_rc1 < 1.11)."))
+                    .arg(&arg_mindur)
+                    .arg(&arg_maxdur)
+                    .arg(arg_first.clone().requires("group").help("Only show the first <num> groups."))
+                    .arg(arg_last.clone().requires("group").help("Only show the last <num> groups."))
+                    .arg(arg_reverse.clone().requires("group").help("Show newest groups first."))
                     .arg(&arg_exact)
+                    .arg(&arg_exclude)
+                    .arg(&arg_pkgfile)
                     .arg(&arg_pkg)
-                    .arg(&arg_limit))
+                    .arg(&arg_limit)
+                    .arg(&arg_algo))
+        .subcommand(SubCommand::with_name("trend")
+                    .aliases(&abbrevs("trend", &[]))
+                    .about("Show packages whose merge time changed the most.")
+                    .long_about("Show packages whose merge time changed the most, from biggest regression to biggest improvement.\n\
+* <package>: merge count, previous average, last average, relative change, slope (time change per build).\n\
+The last N merges are compared with the N before them (less if there isn't enough history).")
+                    .help_message("Show short (-h) or detailed (--help) help.")
+                    .arg(Arg::with_name("window")
+                         .long("window")
+                         .value_name("num")
+                         .takes_value(true)
+                         .default_value("5")
+                         .help("Compare the last N merge times with the N before them."))
+                    .arg(&arg_exact)
+                    .arg(&arg_exclude)
+                    .arg(&arg_pkgfile)
+                    .arg(&arg_pkg))
+        .subcommand(SubCommand::with_name("installed")
+                    .aliases(&abbrevs("installed", &["i"]))
+                    .about("Show currently installed packages, according to the merge log.")
+                    .long_about("Show currently installed packages, reconstructed by replaying merges and unmerges from the log.\n\
+* <package>-<version>: first install date, last build time, days since last rebuild.\n\
+Packages are sorted by last rebuild date, oldest first. This doesn't need access to /var/db/pkg, \
+but the log needs to cover the whole system lifetime (see --from).")
+                    .help_message("Show short (-h) or detailed (--help) help.")
+                    .arg(&arg_exact)
+                    .arg(&arg_exclude)
+                    .arg(&arg_pkgfile)
+                    .arg(&arg_pkg))
+        .subcommand(SubCommand::with_name("audit")
+                    .aliases(&abbrevs("audit", &["a"]))
+                    .about("Compare installed packages according to the log with the package database.")
+                    .long_about("Compare installed packages according to the log (see `installed`) with the package database (see --pkgdir).\n\
+* Not in log:       package is installed but wasn't merged in the parsed log.\n\
+* Not in VDB:       package was merged and not unmerged according to the log, but isn't installed.\n\
+* Version mismatch: package is installed but with different versions than the log says.\n\
+This helps finding gaps in the log history, which affect predictions.")
+                    .help_message("Show short (-h) or detailed (--help) help.")
+                    .arg(&arg_exact)
+                    .arg(&arg_exclude)
+                    .arg(&arg_pkgfile)
+                    .arg(&arg_pkg))
+        .subcommand(SubCommand::with_name("accuracy")
+                    .aliases(&abbrevs("accuracy", &[]))
+                    .about("Measure prediction accuracy against the merge history.")
+                    .long_about("Replay the log, predict each merge using only the merges before it, and compare with the actual merge time.\n\
+* <package>: predicted merge count, mean absolute error, mean percentage error.\n\
+* Total:     predicted merge count, mean absolute error, mean percentage error.\n\
+* Unknown:   count of merges that couldn't be predicted (no previous merge).\n\
+Packages are sorted by total error, worst first. Use this to choose --limit and --predict-algo.")
+                    .help_message("Show short (-h) or detailed (--help) help.")
+                    .arg(&arg_limit)
+                    .arg(&arg_algo)
+                    .arg(Arg::with_name("worst")
+                         .long("worst")
+                         .value_name("num")
+                         .takes_value(true)
+                         .default_value("10")
+                         .help("Show the <num> packages with the biggest total error."))
+                    .arg(&arg_exact)
+                    .arg(&arg_exclude)
+                    .arg(&arg_pkgfile)
+                    .arg(&arg_pkg))
+        .subcommand(SubCommand::with_name("diff")
+                    .aliases(&abbrevs("diff", &[]))
+                    .about("Show which packages changed between --from and --to.")
+                    .long_about("Show which packages changed between --from and --to, by replaying merges and unmerges from the log.\n\
+* New:       package wasn't installed at --from.\n\
+* Upgrade:   package was replaced by a newer version (in the same slot).\n\
+* Downgrade: package was replaced by an older version (in the same slot).\n\
+* Rebuild:   package was merged again with the same version.\n\
+* Removed:   package isn't installed anymore at --to.\n\
+Without --from, all packages installed at --to are shown as new.")
+                    .help_message("Show short (-h) or detailed (--help) help.")
+                    .arg(Arg::with_name("json")
+                         .long("json")
+                         .help("Output changes as a JSON array."))
+                    .arg(&arg_exact)
+                    .arg(&arg_exclude)
+                    .arg(&arg_pkgfile)
+                    .arg(&arg_pkg))
+        .subcommand(SubCommand::with_name("jobs")
+                    .aliases(&abbrevs("jobs", &[]))
+                    .about("Show how many merges ran in parallel.")
+                    .long_about("Show how many merges ran in parallel, per emerge session or per --groupby period.\n\
+* <session>: merge count, max parallel merges, average parallel merges, busy time, total merge time.\n\
+* Total:     merge count, max parallel merges, average parallel merges, busy time, total merge time.\n\
+The busy time is the time during which at least one merge was running. A session ends when no merge starts \
+within 10 minutes of the previous ones finishing. The total covers all sessions, including those hidden by --first/--last. \
+An average close to 1 means that more `emerge --jobs` wouldn't help much.")
+                    .help_message("Show short (-h) or detailed (--help) help.")
+                    .arg(&arg_group)
+                    .arg(arg_first.clone().help("Only show the first <num> sessions or groups."))
+                    .arg(arg_last.clone().help("Only show the last <num> sessions or groups."))
+                    .arg(arg_reverse.clone().help("Show newest sessions or groups first."))
+                    .arg(&arg_exact)
+                    .arg(&arg_exclude)
+                    .arg(&arg_pkgfile)
+                    .arg(&arg_pkg))
+        .subcommand(SubCommand::with_name("gantt")
+                    .aliases(&abbrevs("gantt", &[]))
+                    .about("Draw a timeline of merges in the last session or after --from.")
+                    .long_about("Draw a Gantt chart of merges, one line per merge, to see which merges ran in parallel.\n\
+* <package>-<version>: merge time, bar from merge start to merge end.\n\
+* Total:               session duration, first merge start and last merge end.\n\
+Without --from, only the last emerge session is shown (see `jobs`). With --from, all merges until --to are shown.")
+                    .help_message("Show short (-h) or detailed (--help) help.")
+                    .arg(Arg::with_name("width")
+                         .long("width")
+                         .value_name("num")
+                         .takes_value(true)
+                         .default_value("60")
+                         .help("Width of the chart, in characters."))
+                    .arg(Arg::with_name("svg")
+                         .long("svg")
+                         .value_name("file")
+                         .takes_value(true)
+                         .help("Also write the chart as an SVG image to <file>."))
+                    .arg(&arg_exact)
+                    .arg(&arg_exclude)
+                    .arg(&arg_pkgfile)
+                    .arg(&arg_pkg))
+        .subcommand(SubCommand::with_name("export")
+                    .aliases(&abbrevs("export", &[]))
+                    .about("Export merge times, to import them on another machine.")
+                    .long_about("Write the merge times of each package to stdout, to import them on another machine.\n\
+The format is CSV with a 'package,slot,count,times' header: total merge count, then the last --limit merge times \
+in seconds, oldest first and space-separated.")
+                    .help_message("Show short (-h) or detailed (--help) help.")
+                    .arg(arg_limit.clone().help("Export the last N merge times of each package."))
+                    .arg(&arg_exact)
+                    .arg(&arg_exclude)
+                    .arg(&arg_pkgfile)
+                    .arg(&arg_pkg))
+        .subcommand(SubCommand::with_name("import")
+                    .aliases(&abbrevs("import", &[]))
+                    .about("Import merge times exported on other machines.")
+                    .long_about("Import merge times exported on other machines (see `export`) into --timesfile.\n\
+This replaces previously imported times. `predict` uses them for packages that the local log has never seen, \
+marking them as estimates.")
+                    .help_message("Show short (-h) or detailed (--help) help.")
+                    .arg(Arg::with_name("file")
+                         .takes_value(true)
+                         .multiple(true)
+                         .required(true)
+                         .help("Files written by `emlop export`.")))
 }
 
 /// Generate cli argument parser.
 pub fn build_cli() -> App<'static, 'static> {
-    let c = build_cli_nocomplete();
+    let c = build_cli_nocomplete(true);
     c.subcommand(SubCommand::with_name("complete")
+                 .aliases(&abbrevs("complete", &[]))
                  .about("Generate shell completion script.")
                  .long_about("Write shell completion script to stdout.\n\n\
 You should redirect the output to a file that will be sourced by your shell.\n\
@@ -158,6 +496,34 @@ To apply the changes, either restart you shell or `source` the generated file.
                       .possible_values(&["bash","zsh","fish"])))
 }
 
+/// All subcommand names, to compute their abbreviations.
+const SUBCOMMANDS: [&str; 13] = ["log",
+                                 "predict",
+                                 "stats",
+                                 "trend",
+                                 "installed",
+                                 "audit",
+                                 "accuracy",
+                                 "diff",
+                                 "jobs",
+                                 "gantt",
+                                 "export",
+                                 "import",
+                                 "complete"];
+
+/// Get the abbreviations of a subcommand, to use as aliases.
+///
+/// That's every prefix not shared with another subcommand (like clap's `InferSubcommands` would
+/// accept), plus `extra` ones that take precedence over the other subcommands, like `a` for audit.
+fn abbrevs(name: &'static str, extra: &[&'static str]) -> Vec<&'static str> {
+    (1..name.len()).map(|i| &name[..i])
+                   .filter(|p| {
+                       extra.contains(p)
+                       || SUBCOMMANDS.iter().filter(|s| s.starts_with(p)).count() == 1
+                   })
+                   .collect()
+}
+
 /// Clap validation helper that checks that all chars are valid.
 fn find_invalid(valid: &'static str, s: &str) -> Result<(), String> {
     debug_assert!(valid.is_ascii()); // Because we use `chars()` we need to stick to ascii for `valid`.
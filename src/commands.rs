@@ -1,67 +1,244 @@
-use crate::{date::*, parser::*, proces::*, *};
-use anyhow::bail;
-use std::{collections::{BTreeMap, HashMap},
-          io::{stdin, stdout, Stdout}};
+use crate::{dataset::Dataset, date::*, parser::*, proces::*, sets::Sets, vdb::*, *};
+use anyhow::{bail, Context};
+use std::{cmp::Ordering,
+          collections::{BTreeMap, HashMap, HashSet, VecDeque},
+          fs::read_to_string,
+          io::{stdin, stdout, Stdout},
+          path::Path};
 
 /// Straightforward display of merge events
 ///
 /// We store the start times in a hashmap to compute/print the duration when we reach a stop event.
+/// With `--compare`, we also look at the version that each merge replaced, to tell upgrades from
+/// downgrades and reinstalls, and to display it. That is the version unmerged while the merge was
+/// running, or the last merged version if there was no such unmerge (for example in truncated logs).
 pub fn cmd_list(args: &ArgMatches, subargs: &ArgMatches, st: &Styles) -> Result<bool, Error> {
     let show = value_t!(subargs, "show", Show).unwrap();
+    let compare = subargs.is_present("compare");
+    let filter_dur = filter_dur_fn(subargs);
+    let (include, exclude) = pkg_terms(args, subargs)?;
     let hist = new_hist(args.value_of("logfile").unwrap().into(),
                         value_opt(args, "from", parse_date, st.date_offset),
                         value_opt(args, "to", parse_date, st.date_offset),
-                        show,
-                        subargs.value_of("package"),
+                        Show { unmerge: show.unmerge || (compare && show.merge), ..show },
+                        &include,
+                        &exclude,
                         subargs.is_present("exact"))?;
+    let world = Sets::from_args(args).world();
+    let time = value_t!(subargs, "time", TimeShow).unwrap();
+    let mut limit = Limit::from_args(subargs);
+    let mut out = stdout();
+    let nomark = if compare { "  " } else { "" };
     let mut merges: HashMap<String, i64> = HashMap::new();
     let mut unmerges: HashMap<String, i64> = HashMap::new();
+    let mut last_merged: HashMap<String, Version> = HashMap::new();
+    let mut merging: HashSet<String> = HashSet::new();
+    let mut replaced: HashMap<String, Version> = HashMap::new();
     let mut found_one = false;
     let mut syncstart: i64 = 0;
     for p in hist {
         match p {
-            Hist::MergeStart { ts, key, .. } => {
+            Hist::MergeStart { ts, ref key, .. } => {
                 // This'll overwrite any previous entry, if a merge started but never finished
-                merges.insert(key, ts);
+                if compare {
+                    merging.insert(p.ebuild().to_string());
+                }
+                merges.insert(key.clone(), ts);
             },
             Hist::MergeStop { ts, ref key, .. } => {
+                let start = merges.remove(key);
+                merging.remove(p.ebuild());
+                let started = start.unwrap_or(ts + 1);
+                let (mark, prev) = if compare {
+                    let ver = Version::new(p.version());
+                    let res = match replaced.remove(p.ebuild())
+                                            .or_else(|| last_merged.remove(p.ebuild()))
+                    {
+                        None => ("N ", String::new()),
+                        Some(prev) => match ver.cmp(&prev) {
+                            Ordering::Greater => ("U ", format!(" [{}]", prev)),
+                            Ordering::Less => ("D ", format!(" [{}]", prev)),
+                            Ordering::Equal => ("R ", String::new()),
+                        },
+                    };
+                    last_merged.insert(p.ebuild().to_string(), ver);
+                    res
+                } else {
+                    ("", String::new())
+                };
+                if !filter_dur(ts - started) {
+                    continue;
+                }
                 found_one = true;
-                let started = merges.remove(key).unwrap_or(ts + 1);
+                let merge_p =
+                    if in_world(&world, p.ebuild()) { &st.merge_p } else { &st.mergedep_p };
                 #[rustfmt::skip]
-                writeln!(stdout(), "{} {}{:>9} {}{}{}",
-                         fmt_time(ts, st),
-                         st.dur_p, fmt_duration(st.dur_t, ts - started),
-                         st.merge_p, p.ebuild_version(), st.merge_s).unwrap_or(());
+                let line = format!("{} {}{:>9} {}{}{}{}{}\n",
+                                   fmt_time_range(start, ts, time, st),
+                                   st.dur_p, fmt_duration(st.dur_t, ts - started),
+                                   mark, merge_p, p.ebuild_version(), st.merge_s, prev);
+                if !limit.push(&mut out, line).unwrap_or(false) {
+                    break;
+                }
             },
             Hist::UnmergeStart { ts, key, .. } => {
                 // This'll overwrite any previous entry, if an unmerge started but never finished
                 unmerges.insert(key, ts);
             },
             Hist::UnmergeStop { ts, ref key, .. } => {
+                let start = unmerges.remove(key);
+                let started = start.unwrap_or(ts + 1);
+                if merging.contains(p.ebuild()) {
+                    // Unmerged while merging the same package: this is the version being replaced
+                    replaced.insert(p.ebuild().to_string(), Version::new(p.version()));
+                }
+                if !show.unmerge || !filter_dur(ts - started) {
+                    continue;
+                }
                 found_one = true;
-                let started = unmerges.remove(key).unwrap_or(ts + 1);
                 #[rustfmt::skip]
-                writeln!(stdout(), "{} {}{:>9} {}{}{}",
-                         fmt_time(ts, st),
-                         st.dur_p, fmt_duration(st.dur_t, ts - started),
-                         st.unmerge_p, p.ebuild_version(), st.unmerge_s).unwrap_or(());
+                let line = format!("{} {}{:>9} {}{}{}{}\n",
+                                   fmt_time_range(start, ts, time, st),
+                                   st.dur_p, fmt_duration(st.dur_t, ts - started),
+                                   nomark, st.unmerge_p, p.ebuild_version(), st.unmerge_s);
+                if !limit.push(&mut out, line).unwrap_or(false) {
+                    break;
+                }
             },
             Hist::SyncStart { ts } => {
                 syncstart = ts;
             },
             Hist::SyncStop { ts } => {
+                if !filter_dur(ts - syncstart) {
+                    continue;
+                }
                 found_one = true;
                 #[rustfmt::skip]
-                writeln!(stdout(), "{} {}{:>9}{} Sync",
-                         fmt_time(ts, st),
-                         st.dur_p, fmt_duration(st.dur_t, ts - syncstart), st.dur_s).unwrap_or(());
+                let line = format!("{} {}{:>9}{} {}Sync\n",
+                                   fmt_time_range(Some(syncstart).filter(|&t| t > 0), ts, time, st),
+                                   st.dur_p, fmt_duration(st.dur_t, ts - syncstart), st.dur_s,
+                                   nomark);
+                if !limit.push(&mut out, line).unwrap_or(false) {
+                    break;
+                }
             },
         }
     }
+    limit.flush(&mut out).unwrap_or(());
     Ok(found_one)
 }
 
+/// Collect package search terms from the command line and from `--package-file`
+///
+/// Returns the include and exclude lists. Empty lines and `#` comments in files are ignored. Sets
+/// like `@world` are expanded into exact terms (a regex anchored at both ends, unless `--exact`).
+fn pkg_terms(args: &ArgMatches, subargs: &ArgMatches) -> Result<(Vec<String>, Vec<String>), Error> {
+    let mut include: Vec<String> =
+        subargs.values_of("package").map_or(vec![], |v| v.map(String::from).collect());
+    for file in subargs.values_of("packagefile").into_iter().flatten() {
+        let content = read_to_string(file).with_context(|| format!("Cannot open {:?}", file))?;
+        let len = include.len();
+        include.extend(content.lines()
+                              .map(str::trim)
+                              .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                              .map(String::from));
+        if include.len() == len {
+            warn!("No package found in {:?}", file);
+        }
+    }
+    let exclude = subargs.values_of("exclude").map_or(vec![], |v| v.map(String::from).collect());
+    let sets = Sets::from_args(args);
+    let exact = subargs.is_present("exact");
+    let expand = |terms: Vec<String>| -> Result<Vec<String>, Error> {
+        let mut res = vec![];
+        for term in terms {
+            match term.strip_prefix('@') {
+                Some(set) => {
+                    for atom in sets.expand(set)? {
+                        if exact || atom.starts_with(&['<', '>', '=', '~'][..]) {
+                            res.push(atom);
+                        } else {
+                            res.push(format!("^{}$", regex::escape(&atom)));
+                        }
+                    }
+                },
+                None => res.push(term),
+            }
+        }
+        Ok(res)
+    };
+    Ok((expand(include)?, expand(exclude)?))
+}
+
+/// Select which output items get displayed, according to `--first`, `--last` and `--reverse`.
+///
+/// Items are written out immediately when possible, and only buffered when `--last` or `--reverse`
+/// require it. An item can span multiple lines, as with grouped stats.
+struct Limit {
+    first: Option<usize>,
+    last: Option<usize>,
+    reverse: bool,
+    count: usize,
+    buf: VecDeque<String>,
+}
+impl Limit {
+    fn from_args(subargs: &ArgMatches) -> Self {
+        Self { first: value_opt(subargs, "first", |s, ()| parse_limit(s), ()).map(usize::from),
+               last: value_opt(subargs, "last", |s, ()| parse_limit(s), ()).map(usize::from),
+               reverse: subargs.is_present("reverse"),
+               count: 0,
+               buf: VecDeque::new() }
+    }
+
+    /// Handle one item, returns false once no more items will be displayed.
+    fn push(&mut self, out: &mut impl Write, item: String) -> std::io::Result<bool> {
+        if item.is_empty() {
+            return Ok(true);
+        }
+        if self.first.map_or(false, |f| self.count >= f) {
+            return Ok(false);
+        }
+        self.count += 1;
+        if self.last.is_none() && !self.reverse {
+            out.write_all(item.as_bytes())?;
+        } else {
+            self.buf.push_back(item);
+            if self.last.map_or(false, |l| self.buf.len() > l) {
+                self.buf.pop_front();
+            }
+        }
+        Ok(self.first.map_or(true, |f| self.count < f))
+    }
+
+    /// Write out buffered items.
+    fn flush(&mut self, out: &mut impl Write) -> std::io::Result<()> {
+        while let Some(item) = if self.reverse { self.buf.pop_back() } else { self.buf.pop_front() }
+        {
+            out.write_all(item.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+/// Create a closure that checks a duration against `--min-duration` and `--max-duration`.
+///
+/// Unknown (negative) durations are rejected as soon as one of the bounds is set.
+fn filter_dur_fn(subargs: &ArgMatches) -> impl Fn(i64) -> bool {
+    let min = value_opt(subargs, "min-duration", |s, ()| parse_duration(s), ());
+    let max = value_opt(subargs, "max-duration", |s, ()| parse_duration(s), ());
+    move |d: i64| match (min, max) {
+        (None, None) => true,
+        _ => d >= 0 && min.map_or(true, |m| d >= m) && max.map_or(true, |m| d <= m),
+    }
+}
+
+/// Whether a package is in the world file, or true if we couldn't read the world file.
+fn in_world(world: &Option<HashSet<String>>, pkg: &str) -> bool {
+    world.as_ref().map_or(true, |w| w.contains(pkg))
+}
+
 /// Wrapper to extract stats from a list of data points (durations).
+#[derive(Default)]
 struct Times {
     vals: Vec<i64>,
     count: i64,
@@ -85,26 +262,108 @@ impl Times {
     fn is_empty(&self) -> bool {
         self.count == 0
     }
-    fn clear(&mut self) {
-        self.vals.clear();
-        self.count = 0;
-        self.tot = 0;
+    /// Average of all (positive) data points
+    fn mean(&self) -> i64 {
+        self.tot.checked_div(self.vals.len() as i64).unwrap_or(-1)
     }
-    /// Predict the next data point by looking at past ones
-    fn pred(&self, lim: u16) -> i64 {
-        let (t, c) = self.vals.iter().take(lim as usize).fold((0, 0), |(t, c), v| (t + v, c + 1));
-        if c > 0 {
-            t / c
-        } else {
-            -1 // FIXME Return None
+    /// Predict the next data point by looking at the last `lim` ones, or None if there are none
+    fn pred(&self, lim: u16, algo: PredictAlgo) -> Option<i64> {
+        let vals = &self.vals[..std::cmp::min(lim as usize, self.vals.len())];
+        if vals.is_empty() {
+            return None;
+        }
+        let mean = |s: &[i64]| s.iter().sum::<i64>() / s.len() as i64;
+        let sorted = || {
+            let mut v = vals.to_vec();
+            v.sort_unstable();
+            v
+        };
+        Some(match algo {
+                 PredictAlgo::Mean => mean(vals),
+                 PredictAlgo::Median => {
+                     let v = sorted();
+                     let m = v.len() / 2;
+                     if v.len() % 2 == 0 {
+                         (v[m - 1] + v[m]) / 2
+                     } else {
+                         v[m]
+                     }
+                 },
+                 PredictAlgo::Ewma => {
+                     // Smoothing factor for a span of `vals.len()`, starting from the oldest value
+                     let alpha = 2.0 / (vals.len() as f64 + 1.0);
+                     let first = *vals.last().unwrap() as f64;
+                     vals.iter()
+                         .rev()
+                         .skip(1)
+                         .fold(first, |e, v| alpha * *v as f64 + (1.0 - alpha) * e)
+                         .round() as i64
+                 },
+                 PredictAlgo::Trimmed => {
+                     let v = sorted();
+                     let k = if v.len() >= 3 { std::cmp::max(1, v.len() / 10) } else { 0 };
+                     mean(&v[k..v.len() - k])
+                 },
+             })
+    }
+    /// Range of likely values for the next data point, from the 10th and 90th percentiles of the
+    /// last `lim` ones
+    fn range(&self, lim: u16) -> Option<(i64, i64)> {
+        let mut vals = self.vals[..std::cmp::min(lim as usize, self.vals.len())].to_vec();
+        if vals.is_empty() {
+            return None;
+        }
+        vals.sort_unstable();
+        // Linear interpolation between the closest ranks
+        let pct = |p: f64| {
+            let r = p * (vals.len() - 1) as f64;
+            let (lo, hi) = (vals[r.floor() as usize] as f64, vals[r.ceil() as usize] as f64);
+            (lo + (hi - lo) * r.fract()).round() as i64
+        };
+        Some((pct(0.1), pct(0.9)))
+    }
+    /// Compare the last `n` data points with the `n` ones before them
+    ///
+    /// Returns the relative change of the mean in percent, and the slope in seconds per build
+    /// (linear regression over those data points). With fewer than `2*n` values, the window
+    /// shrinks to half of what's available.
+    fn trend(&self, n: u16) -> Option<(f64, f64)> {
+        let n = std::cmp::min(n as usize, self.vals.len() / 2);
+        if n == 0 {
+            return None;
         }
+        let mean = |s: &[i64]| s.iter().sum::<i64>() as f64 / s.len() as f64;
+        let (last, prev) = (mean(&self.vals[..n]), mean(&self.vals[n..2 * n]));
+        // vals are stored newest first, so x goes backward in time
+        let xmean = (2 * n - 1) as f64 / 2.0;
+        let ymean = (last + prev) / 2.0;
+        let (mut num, mut den) = (0.0, 0.0);
+        for (x, y) in self.vals[..2 * n].iter().enumerate() {
+            num += (x as f64 - xmean) * (*y as f64 - ymean);
+            den += (x as f64 - xmean).powi(2);
+        }
+        Some(((last - prev) * 100.0 / prev, -num / den))
     }
 }
 
+/// Format a signed duration, for values where a negative number is meaningful
+fn fmt_duration_signed(style: DurationStyle, secs: i64) -> String {
+    format!("{}{}", if secs < 0 { '-' } else { '+' }, fmt_duration(style, secs.abs()))
+}
+
+/// Format an optional duration, for values that may not be known
+fn fmt_duration_opt(style: DurationStyle, secs: Option<i64>) -> String {
+    secs.map_or_else(|| String::from("?"), |s| fmt_duration(style, s))
+}
+
 /// Summary display of merge events
 ///
 /// First loop is like cmd_list but we store the merge time for each ebuild instead of printing it.
 /// Then we compute the stats per ebuild, and print that.
+///
+/// With `--versions`, merge times are also stored per version, so that we can compare each version
+/// with the previous one. With `--details`, they are displayed next to the VDB info of each
+/// installed version.
 pub fn cmd_stats(tw: &mut TabWriter<Stdout>,
                  args: &ArgMatches,
                  subargs: &ArgMatches,
@@ -112,16 +371,30 @@ pub fn cmd_stats(tw: &mut TabWriter<Stdout>,
                  -> Result<bool, Error> {
     let show = value_t!(subargs, "show", Show).unwrap();
     let timespan_opt = value_opt(subargs, "group", parse_timespan, ());
+    let filter_dur = filter_dur_fn(subargs);
+    let (include, exclude) = pkg_terms(args, subargs)?;
     let hist = new_hist(args.value_of("logfile").unwrap().into(),
                         value_opt(args, "from", parse_date, st.date_offset),
                         value_opt(args, "to", parse_date, st.date_offset),
                         show,
-                        subargs.value_of("package"),
+                        &include,
+                        &exclude,
                         subargs.is_present("exact"))?;
     let lim = value(subargs, "limit", parse_limit);
+    let algo = value_t!(subargs, "predict-algo", PredictAlgo).unwrap();
+    let details = subargs.is_present("details");
+    let versions = subargs.is_present("versions") || details;
+    let mut limit = Limit::from_args(subargs);
+    let world = Sets::from_args(args).world();
     let mut merge_start: HashMap<String, i64> = HashMap::new();
     let mut unmerge_start: HashMap<String, i64> = HashMap::new();
-    let mut pkg_time: BTreeMap<String, (Times, Times)> = BTreeMap::new();
+    let mut pkg_time: PkgTimes = BTreeMap::new();
+    let mut vdb_slots = VdbSlots::new(args.value_of("pkgdir").unwrap());
+    let mut merged_slots: HashMap<String, String> = HashMap::new();
+    let mut latest_slot: HashMap<String, String> = HashMap::new();
+    let mut pkg_slots: PkgSlots = HashMap::new();
+    let mut merging: HashSet<String> = HashSet::new();
+    let mut replaced: HashMap<String, Vec<i64>> = HashMap::new();
     let mut sync_start: i64 = 0;
     let mut sync_time = Times::new();
     let mut nextts = 0;
@@ -134,68 +407,157 @@ pub fn cmd_stats(tw: &mut TabWriter<Stdout>,
                 curts = t;
             } else if t > nextts {
                 let group_by = timespan.header(curts, st.date_offset);
-                cmd_stats_group(tw, st, lim, show, &group_by, &sync_time, &pkg_time)?;
-                sync_time.clear();
+                let mut buf = vec![];
+                cmd_stats_group(&mut buf, st, &world, lim, algo, show, &pkg_slots, &group_by,
+                                &sync_time, &pkg_time)?;
+                if !limit.push(tw, String::from_utf8(buf)?)? {
+                    limit.flush(tw)?;
+                    return Ok(true);
+                }
+                sync_time = Times::new();
                 pkg_time.clear();
                 nextts = timespan.next(t, st.date_offset);
                 curts = t;
             }
         }
         match p {
-            Hist::MergeStart { ts, key, .. } => {
-                merge_start.insert(key, ts);
+            Hist::MergeStart { ts, ref key, .. } => {
+                merging.insert(p.ebuild().to_owned());
+                merge_start.insert(key.clone(), ts);
             },
-            Hist::MergeStop { ts, ref key, .. } => {
-                if let Some(start_ts) = merge_start.remove(key) {
-                    let (times, _) = pkg_time.entry(p.ebuild().to_owned())
-                                             .or_insert_with(|| (Times::new(), Times::new()));
+            Hist::MergeStop { ts, ref key, ref slot, .. } => {
+                merging.remove(p.ebuild());
+                let slot = slot.clone().or_else(|| vdb_slots.get(p.ebuild(), p.version()));
+                if let Some(slot) = &slot {
+                    merged_slots.insert(p.ebuild_version().to_owned(), slot.clone());
+                    latest_slot.insert(p.ebuild().to_owned(), slot.clone());
+                }
+                // Merges without a known slot go to the latest known one
+                let slot =
+                    slot.or_else(|| latest_slot.get(p.ebuild()).cloned()).unwrap_or_default();
+                if let Some(durs) = replaced.remove(p.ebuild()) {
+                    let (_, times, _) =
+                        slot_entry(&mut pkg_time, &mut pkg_slots, p.ebuild(), slot.clone());
+                    durs.into_iter().for_each(|d| times.insert(d));
+                }
+                if let Some(start_ts) = merge_start.remove(key).filter(|t| filter_dur(ts - t)) {
+                    let (times, _, vers) =
+                        slot_entry(&mut pkg_time, &mut pkg_slots, p.ebuild(), slot);
                     times.insert(ts - start_ts);
+                    if versions {
+                        vers.entry(Version::new(p.version())).or_default().insert(ts - start_ts);
+                    }
                 }
             },
             Hist::UnmergeStart { ts, key, .. } => {
                 unmerge_start.insert(key, ts);
             },
             Hist::UnmergeStop { ts, ref key, .. } => {
-                if let Some(start_ts) = unmerge_start.remove(key) {
-                    let (_, times) = pkg_time.entry(p.ebuild().to_owned())
-                                             .or_insert_with(|| (Times::new(), Times::new()));
-                    times.insert(ts - start_ts);
+                if let Some(start_ts) = unmerge_start.remove(key).filter(|t| filter_dur(ts - t)) {
+                    // Take the slot of the version when it was merged, or from the VDB. Failing
+                    // that, replacing unmerges happen during the merge of the same slot, and
+                    // other unmerges go to an unknown slot.
+                    match merged_slots.get(key)
+                                      .cloned()
+                                      .or_else(|| vdb_slots.get(p.ebuild(), p.version()))
+                    {
+                        None if merging.contains(p.ebuild()) => {
+                            replaced.entry(p.ebuild().to_owned()).or_default().push(ts - start_ts)
+                        },
+                        slot => {
+                            let (_, times, _) = slot_entry(&mut pkg_time,
+                                                           &mut pkg_slots,
+                                                           p.ebuild(),
+                                                           slot.unwrap_or_default());
+                            times.insert(ts - start_ts);
+                        },
+                    }
                 }
             },
             Hist::SyncStart { ts } => {
                 sync_start = ts;
             },
             Hist::SyncStop { ts } => {
-                sync_time.insert(ts - sync_start);
+                if filter_dur(ts - sync_start) {
+                    sync_time.insert(ts - sync_start);
+                }
             },
         }
     }
+    // Unmerges replaced by a merge that didn't finish
+    for (ebuild, durs) in replaced {
+        let (_, times, _) = slot_entry(&mut pkg_time, &mut pkg_slots, &ebuild, String::new());
+        durs.into_iter().for_each(|d| times.insert(d));
+    }
+    if details {
+        let filter_pkg = filter_pkg_fn(&include, &exclude, subargs.is_present("exact"))?;
+        let vdb = read_vdb(Path::new(args.value_of("pkgdir").unwrap()), filter_pkg)?;
+        return cmd_stats_details(tw, st, lim, algo, &pkg_time, vdb);
+    }
     let group_by =
         timespan_opt.map_or(String::new(), |timespan| timespan.header(curts, st.date_offset));
-    cmd_stats_group(tw, st, lim, show, &group_by, &sync_time, &pkg_time)?;
+    let mut buf = vec![];
+    cmd_stats_group(&mut buf, st, &world, lim, algo, show, &pkg_slots, &group_by, &sync_time,
+                    &pkg_time)?;
+    limit.push(tw, String::from_utf8(buf)?)?;
+    limit.flush(tw)?;
     Ok(!pkg_time.is_empty() || !sync_time.is_empty())
 }
 
-fn cmd_stats_group(tw: &mut TabWriter<Stdout>,
+#[allow(clippy::too_many_arguments)]
+fn cmd_stats_group(tw: &mut impl Write,
                    st: &Styles,
+                   world: &Option<HashSet<String>>,
                    lim: u16,
+                   algo: PredictAlgo,
                    show: Show,
+                   pkg_slots: &PkgSlots,
                    group_by: &str,
                    sync_time: &Times,
-                   pkg_time: &BTreeMap<String, (Times, Times)>)
+                   pkg_time: &PkgTimes)
                    -> Result<(), Error> {
     if show.pkg && !pkg_time.is_empty() {
-        for (pkg, (merge, unmerge)) in pkg_time {
+        // Versions of all slots are displayed together, so that deltas carry over slot changes
+        let mut all_vers: HashMap<&str, BTreeMap<&Version, &Times>> = HashMap::new();
+        for ((ebuild, _), (_, _, vers)) in pkg_time.iter().filter(|(_, (_, _, v))| !v.is_empty()) {
+            all_vers.entry(ebuild).or_default().extend(vers);
+        }
+        for (pkg, ebuild, (merge, unmerge, _)) in slot_names(pkg_time, pkg_slots) {
+            let pkg_p = if in_world(world, ebuild) { &st.pkg_p } else { &st.pkgdep_p };
+            if let Some(vers) = all_vers.get_mut(ebuild) {
+                let mut prev = -1;
+                for (ver, times) in std::mem::take(vers) {
+                    let mean = times.mean();
+                    let delta = if prev > 0 && mean > 0 {
+                        format!("{:+.1}%", (mean - prev) as f64 * 100.0 / prev as f64)
+                    } else {
+                        String::new()
+                    };
+                    #[rustfmt::skip]
+                    writeln!(tw, "{}{}{}-{}\t{}{:>5}\t{}{:>10}\t{}{:>8}\t{}{:>7}{}",
+                             group_by,
+                             pkg_p, ebuild, ver,
+                             st.cnt_p, times.count,
+                             st.dur_p, fmt_duration(st.dur_t, times.tot),
+                             st.dur_p, fmt_duration(st.dur_t, mean),
+                             st.cnt_p, delta,
+                             st.dur_s)?;
+                    if mean > 0 {
+                        prev = mean;
+                    }
+                }
+                continue;
+            }
             #[rustfmt::skip]
             writeln!(tw, "{}{}{}\t{}{:>5}\t{}{:>10}\t{}{:>8}\t{}{:>5}\t{}{:>8}\t{}{:>8}{}",
                      group_by,
-                     st.pkg_p, pkg,
+                     pkg_p, pkg,
                      st.cnt_p, merge.count,
                      st.dur_p, fmt_duration(st.dur_t, merge.tot),
-                     st.dur_p, fmt_duration(st.dur_t, merge.pred(lim)),
+                     st.dur_p, fmt_duration_opt(st.dur_t, merge.pred(lim, algo)),
                      st.cnt_p, unmerge.count,
                      st.dur_p, fmt_duration(st.dur_t, unmerge.tot),
-                     st.dur_p, fmt_duration(st.dur_t, unmerge.pred(lim)),
+                     st.dur_p, fmt_duration_opt(st.dur_t, unmerge.pred(lim, algo)),
                      st.dur_s)?;
         }
     }
@@ -204,7 +566,7 @@ fn cmd_stats_group(tw: &mut TabWriter<Stdout>,
         let mut merge_count = 0;
         let mut unmerge_time = 0;
         let mut unmerge_count = 0;
-        for (merge, unmerge) in pkg_time.values() {
+        for (merge, unmerge, _) in pkg_time.values() {
             merge_time += merge.tot;
             merge_count += merge.count;
             unmerge_time += unmerge.tot;
@@ -227,12 +589,469 @@ fn cmd_stats_group(tw: &mut TabWriter<Stdout>,
                  group_by,
                  st.cnt_p, sync_time.count,
                  st.dur_p, fmt_duration(st.dur_t, sync_time.tot),
-                 st.dur_p, fmt_duration(st.dur_t, sync_time.pred(lim)),
+                 st.dur_p, fmt_duration_opt(st.dur_t, sync_time.pred(lim, algo)),
                  st.dur_s)?;
     }
     Ok(())
 }
 
+/// Merge and unmerge times per package and slot, with merge times per version
+type PkgTimes = BTreeMap<(String, String), (Times, Times, BTreeMap<Version, Times>)>;
+
+/// Slots seen so far for each package
+type PkgSlots = HashMap<String, HashSet<String>>;
+
+/// Get the times of a package slot, remembering that slot
+fn slot_entry<'a>(pkg_time: &'a mut PkgTimes,
+                  pkg_slots: &mut PkgSlots,
+                  ebuild: &str,
+                  slot: String)
+                  -> &'a mut (Times, Times, BTreeMap<Version, Times>) {
+    pkg_slots.entry(ebuild.to_owned()).or_default().insert(slot.clone());
+    pkg_time.entry((ebuild.to_owned(), slot)).or_default()
+}
+
+/// Get the display name and ebuild of each `(ebuild, slot)` key
+///
+/// The slot is only displayed once a package was seen with more than one, to keep the output short
+/// while streaming groups. An empty slot stands for unmerges that we couldn't find the slot of, and
+/// is displayed as `?`.
+fn slot_names<'a, T>(map: &'a BTreeMap<(String, String), T>,
+                     pkg_slots: &PkgSlots)
+                     -> Vec<(String, &'a str, &'a T)> {
+    map.iter()
+       .map(|((ebuild, slot), v)| {
+           let name = match pkg_slots.get(ebuild) {
+               Some(s) if s.len() > 1 && slot.is_empty() => format!("{}:?", ebuild),
+               Some(s) if s.len() > 1 => format!("{}:{}", ebuild, slot),
+               _ => ebuild.clone(),
+           };
+           (name, ebuild.as_str(), v)
+       })
+       .collect()
+}
+
+/// Display merge stats of each package, with VDB info about each installed version
+fn cmd_stats_details(tw: &mut TabWriter<Stdout>,
+                     st: &Styles,
+                     lim: u16,
+                     algo: PredictAlgo,
+                     pkg_time: &PkgTimes,
+                     vdb: Vec<VdbPkg>)
+                     -> Result<bool, Error> {
+    let mut installed: BTreeMap<(String, String), Vec<&VdbPkg>> = BTreeMap::new();
+    for p in &vdb {
+        let slot = p.field("SLOT").map(|s| strip_subslot(&s).to_string()).unwrap_or_default();
+        installed.entry((p.ebuild.clone(), slot)).or_default().push(p);
+    }
+    for key in pkg_time.keys() {
+        installed.entry(key.clone()).or_default();
+    }
+    let empty = (Times::new(), Times::new(), BTreeMap::new());
+    let mut pkg_slots = PkgSlots::new();
+    for (ebuild, slot) in installed.keys() {
+        pkg_slots.entry(ebuild.clone()).or_default().insert(slot.clone());
+    }
+    for ((key, vdbpkgs), (pkg, _, _)) in installed.iter().zip(slot_names(&installed, &pkg_slots)) {
+        let (merge, unmerge, vers) = pkg_time.get(key).unwrap_or(&empty);
+        #[rustfmt::skip]
+        writeln!(tw, "{}{}{}\n  Merges:\t{}{}{}, total {}{}{}, predicted {}{}{}\n  Unmerges:\t{}{}{}, total {}{}{}, predicted {}{}{}",
+                 st.pkg_p, pkg, st.pkg_s,
+                 st.cnt_p, merge.count, st.cnt_s,
+                 st.dur_p, fmt_duration(st.dur_t, merge.tot), st.dur_s,
+                 st.dur_p, fmt_duration_opt(st.dur_t, merge.pred(lim, algo)), st.dur_s,
+                 st.cnt_p, unmerge.count, st.cnt_s,
+                 st.dur_p, fmt_duration(st.dur_t, unmerge.tot), st.dur_s,
+                 st.dur_p, fmt_duration_opt(st.dur_t, unmerge.pred(lim, algo)), st.dur_s)?;
+        if vdbpkgs.is_empty() {
+            writeln!(tw, "  Installed:\tnone")?;
+        }
+        for p in vdbpkgs {
+            let times = vers.get(&Version::new(&p.version));
+            #[rustfmt::skip]
+            writeln!(tw, "  Installed:\t{}{}{}, merge count {}{}{}, average {}{}{}",
+                     st.pkg_p, p.version, st.pkg_s,
+                     st.cnt_p, times.map_or(0, |t| t.count), st.cnt_s,
+                     st.dur_p, fmt_duration(st.dur_t, times.map_or(-1, |t| t.mean())), st.dur_s)?;
+            for field in &["SLOT", "repository", "BUILD_TIME", "SIZE", "CHOST", "CFLAGS", "USE"] {
+                let val = match (*field, p.field(field)) {
+                    (_, None) => continue,
+                    ("BUILD_TIME", Some(v)) => v.parse().map_or(v, |ts| fmt_time(ts, st)),
+                    ("SIZE", Some(v)) => v.parse().map_or(v, fmt_size),
+                    (_, Some(v)) => v,
+                };
+                writeln!(tw, "    {}:\t{}", field, val)?;
+            }
+        }
+    }
+    Ok(!installed.is_empty())
+}
+
+/// Format a size in bytes using binary units
+fn fmt_size(bytes: u64) -> String {
+    let mut size = bytes as f64;
+    for unit in &["B", "KiB", "MiB", "GiB"] {
+        if size < 1024.0 {
+            return if *unit == "B" {
+                format!("{} B", bytes)
+            } else {
+                format!("{:.1} {}", size, unit)
+            };
+        }
+        size /= 1024.0;
+    }
+    format!("{:.1} TiB", size)
+}
+
+/// Show how merge times evolve for each package
+///
+/// Gather merge times like cmd_stats does, then compare the last `window` merges of each package
+/// with the ones before, and print packages sorted from the biggest regression to the biggest
+/// improvement.
+pub fn cmd_trend(tw: &mut TabWriter<Stdout>,
+                 args: &ArgMatches,
+                 subargs: &ArgMatches,
+                 st: &Styles)
+                 -> Result<bool, Error> {
+    let (include, exclude) = pkg_terms(args, subargs)?;
+    let hist = new_hist(args.value_of("logfile").unwrap().into(),
+                        value_opt(args, "from", parse_date, st.date_offset),
+                        value_opt(args, "to", parse_date, st.date_offset),
+                        Show { merge: true, ..Show::default() },
+                        &include,
+                        &exclude,
+                        subargs.is_present("exact"))?;
+    let window = value(subargs, "window", parse_limit);
+    let mut merge_start: HashMap<String, i64> = HashMap::new();
+    let mut pkg_time: HashMap<String, Times> = HashMap::new();
+    for p in hist {
+        match p {
+            Hist::MergeStart { ts, key, .. } => {
+                merge_start.insert(key, ts);
+            },
+            Hist::MergeStop { ts, ref key, .. } => {
+                if let Some(start_ts) = merge_start.remove(key) {
+                    pkg_time.entry(p.ebuild().to_owned()).or_default().insert(ts - start_ts);
+                }
+            },
+            _ => unreachable!("Should only receive Hist::{{Start,Stop}}"),
+        }
+    }
+    let mut trends: Vec<(String, Times, f64, f64)> =
+        pkg_time.into_iter()
+                .filter_map(|(pkg, t)| t.trend(window).map(|(pct, slope)| (pkg, t, pct, slope)))
+                .collect();
+    trends.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap().then_with(|| a.0.cmp(&b.0)));
+    for (pkg, times, pct, slope) in &trends {
+        let n = std::cmp::min(window as usize, times.vals.len() / 2);
+        #[rustfmt::skip]
+        writeln!(tw, "{}{}\t{}{:>5}\t{}{:>10}\t{}{:>10}\t{}{:>+7.1}%\t{}{:>10}/build{}",
+                 st.pkg_p, pkg,
+                 st.cnt_p, times.count,
+                 st.dur_p, fmt_duration(st.dur_t, times.vals[n..2 * n].iter().sum::<i64>() / n as i64),
+                 st.dur_p, fmt_duration(st.dur_t, times.vals[..n].iter().sum::<i64>() / n as i64),
+                 st.cnt_p, pct,
+                 st.dur_p, fmt_duration_signed(st.dur_t, slope.round() as i64),
+                 st.dur_s)?;
+    }
+    Ok(!trends.is_empty())
+}
+
+/// Installed packages, see `replay_installed()`
+type Installed = HashMap<(String, String), (i64, i64, i64, Option<String>)>;
+
+/// Reconstruct the currently installed packages by replaying merges and unmerges
+///
+/// A version is installed by a merge and removed by an unmerge of the same version, unless that
+/// unmerge happens while the same version is being merged (a reinstall). Returns a map of
+/// (ebuild, version) to (first install, last rebuild, last build duration, slot if known).
+fn replay_installed(hist: impl IntoIterator<Item = Hist>) -> Installed {
+    let mut merge_start: HashMap<String, i64> = HashMap::new();
+    let mut installed: Installed = HashMap::new();
+    let mut merging: HashMap<String, String> = HashMap::new();
+    for p in hist {
+        match p {
+            Hist::MergeStart { ts, ref key, .. } => {
+                merging.insert(p.ebuild().to_string(), p.version().to_string());
+                merge_start.insert(key.clone(), ts);
+            },
+            Hist::MergeStop { ts, ref key, ref slot, .. } => {
+                merging.remove(p.ebuild());
+                let dur = merge_start.remove(key).map_or(-1, |start_ts| ts - start_ts);
+                let k = (p.ebuild().to_string(), p.version().to_string());
+                let first = installed.get(&k).map_or(ts, |i| i.0);
+                installed.insert(k, (first, ts, dur, slot.clone()));
+            },
+            Hist::UnmergeStop { .. } => {
+                // Unmerging the version being merged is a reinstall, keep the first install date
+                if merging.get(p.ebuild()).map_or(true, |v| v != p.version()) {
+                    installed.remove(&(p.ebuild().to_string(), p.version().to_string()));
+                }
+            },
+            Hist::UnmergeStart { .. } => (),
+            _ => unreachable!("Should only receive Hist::{{Start,Stop}} and Hist::Unmerge{{Start,Stop}}"),
+        }
+    }
+    installed
+}
+
+/// Show the currently installed packages, reconstructed from the merge history
+///
+/// Rows are sorted by last rebuild date, oldest first.
+pub fn cmd_installed(tw: &mut TabWriter<Stdout>,
+                     args: &ArgMatches,
+                     subargs: &ArgMatches,
+                     st: &Styles)
+                     -> Result<bool, Error> {
+    let (include, exclude) = pkg_terms(args, subargs)?;
+    let hist = new_hist(args.value_of("logfile").unwrap().into(),
+                        value_opt(args, "from", parse_date, st.date_offset),
+                        value_opt(args, "to", parse_date, st.date_offset),
+                        Show { merge: true, unmerge: true, ..Show::default() },
+                        &include,
+                        &exclude,
+                        subargs.is_present("exact"))?;
+    let world = Sets::from_args(args).world();
+    let now = epoch_now();
+    let installed = replay_installed(hist);
+    let mut rows: Vec<_> = installed.into_iter().collect();
+    rows.sort_by(|a, b| (a.1).1.cmp(&(b.1).1).then_with(|| a.0.cmp(&b.0)));
+    for ((ebuild, version), (first, last, dur, _)) in &rows {
+        let pkg_p = if in_world(&world, ebuild) { &st.pkg_p } else { &st.pkgdep_p };
+        #[rustfmt::skip]
+        writeln!(tw, "{}{}-{}\t{}\t{}{:>9}\t{}{:>6}{}",
+                 pkg_p, ebuild, version,
+                 fmt_time(*first, st),
+                 st.dur_p, fmt_duration(st.dur_t, *dur),
+                 st.cnt_p, (now - last) / 86400,
+                 st.cnt_s)?;
+    }
+    Ok(!rows.is_empty())
+}
+
+/// Compare the installed packages according to the log with the ones in the VDB
+///
+/// Reports packages in the VDB without a log record, packages missing from the VDB, and packages
+/// whose installed versions differ. These point to gaps in the log (rotation, manual edits...).
+pub fn cmd_audit(tw: &mut TabWriter<Stdout>,
+                 args: &ArgMatches,
+                 subargs: &ArgMatches,
+                 st: &Styles)
+                 -> Result<bool, Error> {
+    let (include, exclude) = pkg_terms(args, subargs)?;
+    let exact = subargs.is_present("exact");
+    let hist = new_hist(args.value_of("logfile").unwrap().into(),
+                        value_opt(args, "from", parse_date, st.date_offset),
+                        value_opt(args, "to", parse_date, st.date_offset),
+                        Show { merge: true, unmerge: true, ..Show::default() },
+                        &include,
+                        &exclude,
+                        exact)?;
+    let vdb = read_vdb(Path::new(args.value_of("pkgdir").unwrap()),
+                       filter_pkg_fn(&include, &exclude, exact)?)?;
+    let mut pkgs: BTreeMap<String, (Vec<Version>, Vec<Version>)> = BTreeMap::new();
+    for (ebuild, version) in replay_installed(hist).into_keys() {
+        pkgs.entry(ebuild).or_default().0.push(Version::new(&version));
+    }
+    for p in vdb {
+        pkgs.entry(p.ebuild).or_default().1.push(Version::new(&p.version));
+    }
+    let mut found = false;
+    for (ebuild, (mut log, mut vdb)) in pkgs {
+        log.sort();
+        vdb.sort();
+        if log == vdb {
+            continue;
+        }
+        found = true;
+        let join = |v: &[Version]| v.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(" ");
+        let (msg, vers) = match (log.is_empty(), vdb.is_empty()) {
+            (true, _) => ("Not in log", join(&vdb)),
+            (_, true) => ("Not in VDB", join(&log)),
+            _ => ("Version mismatch", format!("log: {}, VDB: {}", join(&log), join(&vdb))),
+        };
+        #[rustfmt::skip]
+        writeln!(tw, "{}\t{}{}\t{}{}{}",
+                 msg,
+                 st.pkg_p, ebuild,
+                 st.cnt_p, vers, st.cnt_s)?;
+    }
+    Ok(found)
+}
+
+/// Kind of package change reported by `cmd_diff()`, in display order
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+enum Change {
+    New,
+    Upgrade,
+    Downgrade,
+    Rebuild,
+    Removed,
+}
+impl Change {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::New => "New",
+            Self::Upgrade => "Upgrade",
+            Self::Downgrade => "Downgrade",
+            Self::Rebuild => "Rebuild",
+            Self::Removed => "Removed",
+        }
+    }
+}
+
+/// Show what changed on the system between `--from` and `--to`
+///
+/// We replay the log up to each date (see `replay_installed()`) and compare the installed versions
+/// of each package. Added and removed versions are paired as up/downgrades when they are in the
+/// same slot, or when there is only one of each.
+pub fn cmd_diff(tw: &mut TabWriter<Stdout>,
+                args: &ArgMatches,
+                subargs: &ArgMatches,
+                st: &Styles)
+                -> Result<bool, Error> {
+    let (include, exclude) = pkg_terms(args, subargs)?;
+    let exact = subargs.is_present("exact");
+    let from = value_opt(args, "from", parse_date, st.date_offset);
+    let to = value_opt(args, "to", parse_date, st.date_offset);
+    let replay = |max: Option<i64>,
+                  unmerged: &mut Vec<(String, String, i64)>|
+     -> Result<_, Error> {
+        let hist = new_hist(args.value_of("logfile").unwrap().into(),
+                            None,
+                            max,
+                            Show { merge: true, unmerge: true, ..Show::default() },
+                            &include,
+                            &exclude,
+                            exact)?;
+        Ok(replay_installed(hist.into_iter().inspect(|p| {
+                                                if let Hist::UnmergeStop { ts, .. } = p {
+                                                    if from.map_or(false, |f| *ts >= f) {
+                                                        unmerged.push((p.ebuild().to_string(),
+                                                                       p.version().to_string(),
+                                                                       *ts));
+                                                    }
+                                                }
+                                            })))
+    };
+    let mut unmerged = vec![];
+    let mut before = match from {
+        Some(f) => replay(Some(f - 1), &mut unmerged)?,
+        None => HashMap::new(),
+    };
+    let after = replay(to, &mut unmerged)?;
+    // Versions unmerged during the window were installed at its start, even if the log doesn't say
+    // when they were merged (log starting after their install).
+    for (ebuild, version, ts) in unmerged {
+        let k = (ebuild, version);
+        if !before.contains_key(&k) && after.get(&k).map_or(true, |a| a.0 > ts) {
+            before.insert(k, (ts, ts, -1, None));
+        }
+    }
+    type Vers = Vec<(Version, Option<String>)>;
+    let mut pkgs: BTreeMap<String, (Vers, Vers, Vers)> = BTreeMap::new();
+    for ((ebuild, version), (_, _, _, slot)) in &before {
+        if !after.contains_key(&(ebuild.clone(), version.clone())) {
+            pkgs.entry(ebuild.clone()).or_default().0.push((Version::new(version), slot.clone()));
+        }
+    }
+    for ((ebuild, version), (_, last, _, slot)) in &after {
+        let (_, added, rebuilt) = pkgs.entry(ebuild.clone()).or_default();
+        if !before.contains_key(&(ebuild.clone(), version.clone())) {
+            added.push((Version::new(version), slot.clone()));
+        } else if from.map_or(false, |f| *last >= f) {
+            rebuilt.push((Version::new(version), slot.clone()));
+        }
+    }
+    // Package, change, slot, old version, new version
+    type Row = (String, Change, Option<String>, Option<Version>, Option<Version>);
+    let mut changes: Vec<Row> = vec![];
+    for (ebuild, (mut removed, mut added, rebuilt)) in pkgs {
+        removed.sort();
+        added.sort();
+        let mut rows = vec![];
+        // Pair by slot first, then the last remaining versions
+        for (new, slot) in std::mem::take(&mut added) {
+            let pos = removed.iter().position(|(_, s)| slot.is_some() && *s == slot);
+            match pos {
+                Some(pos) => rows.push((slot, Some(removed.remove(pos).0), Some(new))),
+                None => added.push((new, slot)),
+            }
+        }
+        if added.len() == 1 && removed.len() == 1 {
+            let ((new, slot), (old, oldslot)) = (added.remove(0), removed.remove(0));
+            rows.push((slot.or(oldslot), Some(old), Some(new)));
+        }
+        rows.extend(added.into_iter().map(|(new, slot)| (slot, None, Some(new))));
+        rows.extend(removed.into_iter().map(|(old, slot)| (slot, Some(old), None)));
+        rows.extend(rebuilt.into_iter().map(|(v, slot)| (slot, Some(v.clone()), Some(v))));
+        for (slot, old, new) in rows {
+            let change = match (&old, &new) {
+                (None, _) => Change::New,
+                (_, None) => Change::Removed,
+                (Some(o), Some(n)) if o < n => Change::Upgrade,
+                (Some(o), Some(n)) if o > n => Change::Downgrade,
+                _ => Change::Rebuild,
+            };
+            changes.push((ebuild.clone(), change, slot, old, new));
+        }
+    }
+    changes.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.cmp(&b.1)).then_with(|| a.4.cmp(&b.4)));
+    if subargs.is_present("json") {
+        let opt = |o: Option<String>| o.map_or(String::from("null"), |s| fmt_json_str(&s));
+        writeln!(tw, "[")?;
+        for (i, (ebuild, change, slot, old, new)) in changes.iter().enumerate() {
+            writeln!(tw,
+                     "  {{\"change\": {}, \"package\": {}, \"slot\": {}, \"old\": {}, \"new\": {}}}{}",
+                     fmt_json_str(&change.name().to_lowercase()),
+                     fmt_json_str(ebuild),
+                     opt(slot.clone()),
+                     opt(old.as_ref().map(|v| v.to_string())),
+                     opt(new.as_ref().map(|v| v.to_string())),
+                     if i + 1 < changes.len() { "," } else { "" })?;
+        }
+        writeln!(tw, "]")?;
+        return Ok(!changes.is_empty());
+    }
+    let mut count: HashMap<&str, usize> = HashMap::new();
+    for c in &changes {
+        *count.entry(&c.0).or_default() += 1;
+    }
+    for (ebuild, change, slot, old, new) in &changes {
+        let pkg = match slot {
+            Some(slot) if count[ebuild.as_str()] > 1 => format!("{}:{}", ebuild, slot),
+            _ => ebuild.clone(),
+        };
+        let vers = match (old, new) {
+            (Some(o), Some(n)) if o != n => format!("{} -> {}", o, n),
+            (Some(v), _) | (_, Some(v)) => v.to_string(),
+            _ => unreachable!("Change without a version"),
+        };
+        #[rustfmt::skip]
+        writeln!(tw, "{}\t{}{}\t{}{}{}",
+                 change.name(),
+                 st.pkg_p, pkg,
+                 st.cnt_p, vers, st.cnt_s)?;
+    }
+    Ok(!changes.is_empty())
+}
+
+/// Format a string as a JSON string literal
+fn fmt_json_str(s: &str) -> String {
+    let mut res = String::with_capacity(s.len() + 2);
+    res.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            c if (c as u32) < 0x20 => res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
 /// Predict future merge time
 ///
 /// Very similar to cmd_summary except we want total build time for a list of ebuilds.
@@ -243,6 +1062,10 @@ pub fn cmd_predict(tw: &mut TabWriter<Stdout>,
                    -> Result<bool, Error> {
     let now = epoch_now();
     let lim = value(subargs, "limit", parse_limit);
+    let algo = value_t!(subargs, "predict-algo", PredictAlgo).unwrap();
+    let default = value_opt(subargs, "default-time", |s, ()| parse_duration(s), ());
+    let (include, exclude) = pkg_terms(args, subargs)?;
+    let filter_pkg = filter_pkg_fn(&include, &exclude, subargs.is_present("exact"))?;
 
     // Gather and print info about current merge process.
     let mut cms = std::i64::MAX;
@@ -255,102 +1078,768 @@ pub fn cmd_predict(tw: &mut TabWriter<Stdout>,
                  fmt_duration(st.dur_t, now - i.start),
                  st.dur_s)?;
     }
-    if cms == std::i64::MAX && atty::is(atty::Stream::Stdin) {
-        writeln!(tw, "No ongoing merge found")?;
+    if cms == std::i64::MAX && atty::is(atty::Stream::Stdin) {
+        writeln!(tw, "No ongoing merge found")?;
+        return Ok(false);
+    }
+
+    // Parse emerge log.
+    let hist = new_hist(args.value_of("logfile").unwrap().into(),
+                        value_opt(args, "from", parse_date, st.date_offset),
+                        value_opt(args, "to", parse_date, st.date_offset),
+                        Show { merge: true, ..Show::default() },
+                        &[],
+                        &[],
+                        false)?;
+    let mut started: BTreeMap<(String, Version), i64> = BTreeMap::new();
+    let mut times: HashMap<String, Times> = HashMap::new();
+    let mut slot_times: HashMap<(String, String), Times> = HashMap::new();
+    let mut vdb_slots = VdbSlots::new(args.value_of("pkgdir").unwrap());
+    // Every merge, and how fast it was compared to its prediction, to detect hardware changes
+    let mut merges: Vec<(String, Option<String>, i64, i64)> = vec![];
+    let mut speeds: Vec<(i64, f64)> = vec![];
+    for p in hist {
+        match p {
+            // We're ignoring iter here (reducing the start->stop matching accuracy) because there's no iter in the pretend output.
+            Hist::MergeStart { ts, .. } => {
+                started.insert((p.ebuild().to_string(), Version::new(p.version())), ts);
+            },
+            Hist::MergeStop { ts, ref slot, .. } => {
+                let k = (p.ebuild().to_string(), Version::new(p.version()));
+                if let Some(start_ts) = started.remove(&k) {
+                    let slot = slot.clone().or_else(|| vdb_slots.get(p.ebuild(), p.version()));
+                    if let Some(slot) = &slot {
+                        slot_times.entry((k.0.clone(), slot.clone()))
+                                  .or_default()
+                                  .insert(ts - start_ts);
+                    }
+                    let timevec = times.entry(k.0.clone()).or_default();
+                    if let Some(pred) = timevec.pred(lim, algo).filter(|_| ts > start_ts) {
+                        speeds.push((ts, (ts - start_ts) as f64 / pred as f64));
+                    }
+                    timevec.insert(ts - start_ts);
+                    merges.push((k.0, slot, ts, ts - start_ts));
+                }
+            },
+            _ => unreachable!("Should only receive Hist::{{Start,Stop}}"),
+        }
+    }
+    // Rescale merges that happened before a hardware change, as if they had run on today's machine
+    if let Some((step_ts, factor)) = speed_step(&speeds) {
+        info!("Speed changed around {}, scaling older merge times by {:.2}",
+              fmt_time(step_ts, st),
+              factor);
+        times.clear();
+        slot_times.clear();
+        for (ebuild, slot, ts, dur) in merges {
+            let dur = if ts < step_ts { scale(dur, factor) } else { dur };
+            if let Some(slot) = slot {
+                slot_times.entry((ebuild.clone(), slot)).or_default().insert(dur);
+            }
+            times.entry(ebuild).or_default().insert(dur);
+        }
+    }
+    // Load merge times from other machines, scaled to the speed of this one
+    let timesfile = Path::new(args.value_of("timesfile").unwrap());
+    let dataset = if timesfile.exists() {
+        Dataset::read(timesfile).unwrap_or_else(|e| {
+                                    warn!("Ignoring imported merge times: {:#}", e);
+                                    Dataset::default()
+                                })
+    } else {
+        Dataset::default()
+    };
+    let factor = match value_opt(subargs, "speed-factor", |s, ()| parse_factor(s), ()) {
+        Some(f) => f,
+        None => speed_ratio(&times, &dataset, lim, algo).unwrap_or(1.0),
+    };
+    let imported_note = if (factor - 1.0).abs() < 0.005 {
+        String::from("imported")
+    } else {
+        format!("imported x{:.2}", factor)
+    };
+    let mut imported: HashMap<String, Times> = HashMap::new();
+    let mut imported_slot: HashMap<(String, String), Times> = HashMap::new();
+    for ((ebuild, slot), (_, durs)) in dataset.times {
+        for d in durs.into_iter().map(|d| scale(d, factor)) {
+            imported.entry(ebuild.clone()).or_default().insert(d);
+            imported_slot.entry((ebuild.clone(), slot.clone())).or_default().insert(d);
+        }
+    }
+    let jobs = value(subargs, "jobs", parse_jobs);
+
+    // Parse list of pending merges (from stdin or from emerge log filtered by cms).
+    // We collect immediately to deal with type mismatches; it should be a small list anyway.
+    let pretend: Vec<Pretend> = if atty::is(atty::Stream::Stdin) {
+        started.iter()
+               .filter(|&(_, t)| *t > cms)
+               .map(|(&(ref e, ref v), _)| Pretend { ebuild: e.to_string(),
+                                                     version: v.to_string(),
+                                                     slot: None })
+               .collect()
+    } else {
+        new_pretend(stdin(), "STDIN")
+    };
+    let pretend = pretend.into_iter().filter(|p| filter_pkg(&p.ebuild, &p.version));
+
+    // Gather and print per-package and indivudual stats.
+    let mut totcount = 0;
+    let mut totunknown = 0;
+    let mut totestimated = 0;
+    let mut totelapsed = 0;
+    let mut queue: Vec<(i64, i64, i64)> = vec![];
+    for Pretend { ebuild, version, slot } in pretend {
+        // Find the elapsed time, if any (heuristic is that emerge process started before
+        // this merge finished, it's not failsafe but IMHO no worse than genlop).
+        let k = (ebuild, Version::new(&version));
+        let (elapsed, elapsed_fmt) = match started.remove(&k) {
+            Some(s) if s > cms => {
+                (now - s, format!(" - {}{}{}", st.dur_p, fmt_duration(st.dur_t, now - s), st.dur_s))
+            },
+            _ => (0, "".into()),
+        };
+        let (ebuild, version) = k;
+
+        // Find the predicted time and range (using the same slot if possible, or estimated from
+        // other packages) and adjust counters
+        totcount += 1;
+        let pred_from =
+            |tv: &Times, note: &str| Some((tv.pred(lim, algo)?, tv.range(lim)?, note.to_string()));
+        let slot_key = slot.map(|s| (ebuild.clone(), s));
+        let pred =
+            slot_key.as_ref()
+                    .and_then(|k| slot_times.get(k))
+                    .and_then(|tv| pred_from(tv, ""))
+                    .or_else(|| times.get(&ebuild).and_then(|tv| pred_from(tv, "")))
+                    .or_else(|| {
+                        slot_key.as_ref()
+                                .and_then(|k| imported_slot.get(k))
+                                .and_then(|tv| pred_from(tv, &imported_note))
+                    })
+                    .or_else(|| imported.get(&ebuild).and_then(|tv| pred_from(tv, &imported_note)))
+                    .or_else(|| estimate(&ebuild, &times, lim, algo, default));
+        let (pred_fmt, range_fmt) = match pred {
+            Some((pred, (low, high), note)) => {
+                queue.push(remaining(elapsed, pred, (low, high)));
+                totelapsed += elapsed;
+                let (pred_fmt, note_fmt) = if note.is_empty() {
+                    (fmt_duration(st.dur_t, pred), String::new())
+                } else {
+                    totestimated += 1;
+                    (format!("~{}", fmt_duration(st.dur_t, pred)), format!(" ({})", note))
+                };
+                (pred_fmt,
+                 format!("\t{}{} - {}{}{}{}",
+                         st.dur_p,
+                         fmt_duration(st.dur_t, low),
+                         fmt_duration(st.dur_t, high),
+                         st.dur_s,
+                         note_fmt,
+                         fmt_progress(st, now, pred, elapsed)))
+            },
+            None => {
+                totunknown += 1;
+                ("?".into(), String::new())
+            },
+        };
+
+        // Done
+        #[rustfmt::skip]
+        writeln!(tw, "{}{}-{}\t{}{:>9}{}{}{}",
+                 st.pkg_p, ebuild, version,
+                 st.dur_p, pred_fmt,
+                 st.dur_s, elapsed_fmt, range_fmt)?;
+    }
+    if totcount > 0 {
+        let totpredict = schedule(queue.iter().map(|q| q.0), jobs);
+        let totlow = schedule(queue.iter().map(|q| q.1), jobs);
+        let tothigh = schedule(queue.iter().map(|q| q.2), jobs);
+        let estimated_fmt = if totestimated > 0 {
+            format!(", {}{}{} estimated", st.cnt_p, totestimated, st.cnt_s)
+        } else {
+            String::new()
+        };
+        let jobs_fmt = if jobs > 1 {
+            format!(", {}{}{} jobs", st.cnt_p, jobs, st.cnt_s)
+        } else {
+            String::new()
+        };
+        #[rustfmt::skip]
+        writeln!(tw, "Estimate for {}{}{} ebuilds ({}{}{} unknown{}, {}{}{} elapsed{})\t{}{:>9}{} @ {}{}{}",
+                 st.cnt_p, totcount, st.cnt_s,
+                 st.cnt_p, totunknown, st.cnt_s,
+                 estimated_fmt,
+                 st.dur_p, fmt_duration(st.dur_t, totelapsed), st.dur_s,
+                 jobs_fmt,
+                 st.dur_p, fmt_duration(st.dur_t, totpredict), st.dur_s,
+                 st.dur_p, fmt_time(now + totpredict, st), st.dur_s)?;
+        #[rustfmt::skip]
+        writeln!(tw, "Estimate range\t{}{:>9} - {}{} @ {}{}{} - {}{}{}",
+                 st.dur_p, fmt_duration(st.dur_t, totlow), fmt_duration(st.dur_t, tothigh), st.dur_s,
+                 st.dur_p, fmt_time(now + totlow, st), st.dur_s,
+                 st.dur_p, fmt_time(now + tothigh, st), st.dur_s)?;
+    } else {
+        writeln!(tw, "No pretended merge found")?;
+    }
+    Ok(totcount > 0)
+}
+
+/// Time left for a merge that has already been running for `elapsed`, as (pred, low, high)
+///
+/// An overdue merge is expected to need the margin between the prediction and the high estimate.
+/// That stays bounded however late the merge is, but still counts towards the total.
+fn remaining(elapsed: i64, pred: i64, (low, high): (i64, i64)) -> (i64, i64, i64) {
+    let left = |t: i64| std::cmp::max(t - elapsed, 0);
+    let margin = std::cmp::max(high - pred, 0);
+    let pred_left = if elapsed < pred { pred - elapsed } else { margin };
+    (pred_left, left(low), std::cmp::max(left(high), pred_left))
+}
+
+/// Format how far along an ongoing merge is: percent done and finish time, or how late it is
+fn fmt_progress(st: &Styles, now: i64, pred: i64, elapsed: i64) -> String {
+    if elapsed <= 0 || pred <= 0 {
+        String::new()
+    } else if elapsed > pred {
+        format!(", overdue by {}{}{}", st.dur_p, fmt_duration(st.dur_t, elapsed - pred), st.dur_s)
+    } else {
+        format!(", {}{}%{} done @ {}{}{}",
+                st.cnt_p,
+                elapsed * 100 / pred,
+                st.cnt_s,
+                st.dur_p,
+                fmt_time(now + pred - elapsed, st),
+                st.dur_s)
+    }
+}
+
+/// Estimate the merge time of a package that was never merged, with a note saying how
+///
+/// Try the `-bin` variant of the package (or the source variant of a `-bin` package), then the
+/// median prediction of packages in the same category, then the `--default-time`.
+fn estimate(ebuild: &str,
+            times: &HashMap<String, Times>,
+            lim: u16,
+            algo: PredictAlgo,
+            default: Option<i64>)
+            -> Option<(i64, (i64, i64), String)> {
+    let similar = match ebuild.strip_suffix("-bin") {
+        Some(src) => src.to_string(),
+        None => format!("{}-bin", ebuild),
+    };
+    if let Some(tv) = times.get(&similar) {
+        if let (Some(pred), Some(range)) = (tv.pred(lim, algo), tv.range(lim)) {
+            return Some((pred, range, format!("like {}", similar)));
+        }
+    }
+    let categ = ebuild.split('/').next().unwrap_or_default();
+    let mut categ_times = Times::new();
+    for (e, tv) in times {
+        if e.split('/').next() == Some(categ) {
+            if let Some(pred) = tv.pred(lim, algo) {
+                categ_times.insert(pred);
+            }
+        }
+    }
+    if let (Some(pred), Some(range)) =
+        (categ_times.pred(u16::MAX, PredictAlgo::Median), categ_times.range(u16::MAX))
+    {
+        return Some((pred, range, format!("{}/* median", categ)));
+    }
+    default.map(|d| (d, (d, d), String::from("default")))
+}
+
+/// Write the last merge times of each package and slot, for `emlop import` on another machine
+pub fn cmd_export(args: &ArgMatches, subargs: &ArgMatches, st: &Styles) -> Result<bool, Error> {
+    let lim = value(subargs, "limit", parse_limit) as usize;
+    let (include, exclude) = pkg_terms(args, subargs)?;
+    let hist = new_hist(args.value_of("logfile").unwrap().into(),
+                        value_opt(args, "from", parse_date, st.date_offset),
+                        value_opt(args, "to", parse_date, st.date_offset),
+                        Show { merge: true, ..Show::default() },
+                        &include,
+                        &exclude,
+                        subargs.is_present("exact"))?;
+    let mut vdb_slots = VdbSlots::new(args.value_of("pkgdir").unwrap());
+    let mut merge_start: HashMap<String, i64> = HashMap::new();
+    let mut times: BTreeMap<(String, String), Times> = BTreeMap::new();
+    for p in hist {
+        match p {
+            Hist::MergeStart { ts, key, .. } => {
+                merge_start.insert(key, ts);
+            },
+            Hist::MergeStop { ts, ref key, ref slot, .. } => {
+                if let Some(start_ts) = merge_start.remove(key) {
+                    let slot = slot.clone().or_else(|| vdb_slots.get(p.ebuild(), p.version()));
+                    times.entry((p.ebuild().to_string(), slot.unwrap_or_default()))
+                         .or_default()
+                         .insert(ts - start_ts);
+                }
+            },
+            _ => unreachable!("Should only receive Hist::{{Start,Stop}}"),
+        }
+    }
+    let mut dataset = Dataset::default();
+    for ((ebuild, slot), tv) in &times {
+        let last: Vec<i64> = tv.vals.iter().take(lim).rev().copied().collect();
+        dataset.insert(ebuild, slot, tv.count as u64, &last);
+    }
+    dataset.write(&mut stdout())?;
+    Ok(!times.is_empty())
+}
+
+/// Replace the imported merge times (used by `predict`) with the content of the given files
+pub fn cmd_import(args: &ArgMatches, subargs: &ArgMatches) -> Result<bool, Error> {
+    let mut datasets = vec![];
+    for file in subargs.values_of("file").unwrap() {
+        datasets.push(Dataset::read(Path::new(file))?);
+    }
+    let dataset = Dataset::merge(datasets);
+    let timesfile = Path::new(args.value_of("timesfile").unwrap());
+    if let Some(dir) = timesfile.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Cannot create {:?}", dir))?;
+    }
+    let mut out =
+        std::fs::File::create(timesfile).with_context(|| format!("Cannot write {:?}", timesfile))?;
+    dataset.write(&mut out)?;
+    writeln!(stdout(), "Imported {} packages into {:?}", dataset.times.len(), timesfile)?;
+    Ok(!dataset.times.is_empty())
+}
+
+/// Minimum number of packages known on both machines to compute a speed ratio
+const SPEED_MIN_COMMON: usize = 5;
+/// Minimum number of merges on each side of a speed step
+const SPEED_MIN_MERGES: usize = 50;
+/// Minimum duration on each side of a speed step, so that one unusual session isn't mistaken for
+/// a hardware change
+const SPEED_MIN_SPAN: i64 = 7 * 24 * 3600;
+/// Minimum score of a speed step, as a multiple of the noise (standard deviation of merge speeds)
+const SPEED_MIN_SCORE: f64 = 8.0;
+/// Minimum factor of a speed step
+const SPEED_MIN_FACTOR: f64 = 1.2;
+
+/// How much slower merges in `new` are compared to the imported `old` ones
+///
+/// This is the median ratio of predictions, amongst packages found in both.
+fn speed_ratio(new: &HashMap<String, Times>,
+               old: &Dataset,
+               lim: u16,
+               algo: PredictAlgo)
+               -> Option<f64> {
+    let mut old_times: HashMap<&str, Times> = HashMap::new();
+    for ((ebuild, _), (_, durs)) in &old.times {
+        durs.iter().for_each(|d| old_times.entry(ebuild).or_default().insert(*d));
+    }
+    let mut ratios: Vec<f64> = new.iter()
+                                  .filter_map(|(ebuild, tv)| {
+                                      let n = tv.pred(lim, algo)?;
+                                      let o = old_times.get(ebuild.as_str())?.pred(lim, algo)?;
+                                      Some(n as f64 / o as f64)
+                                  })
+                                  .collect();
+    if ratios.len() < SPEED_MIN_COMMON {
+        return None;
+    }
+    ratios.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    Some(ratios[ratios.len() / 2])
+}
+
+/// Find a lasting change in merge speed, like a CPU upgrade
+///
+/// `speeds` holds the timestamp of each merge and its duration relative to the prediction at the
+/// time. We look for the split that best separates the mean log-ratio before and after (weighted
+/// by sample sizes), and return its timestamp with the factor to apply to older merges. The change
+/// must be far above the noise (merge times vary a lot from one session to the next), and of at
+/// least 20%.
+fn speed_step(speeds: &[(i64, f64)]) -> Option<(i64, f64)> {
+    if speeds.len() < 2 * SPEED_MIN_MERGES {
+        return None;
+    }
+    let (first, last) = (speeds[0].0, speeds[speeds.len() - 1].0);
+    let logs: Vec<f64> = speeds.iter().map(|(_, r)| r.ln().clamp(-2.0, 2.0)).collect();
+    let n = logs.len() as f64;
+    let total: f64 = logs.iter().sum();
+    let sd = (logs.iter().map(|l| (l - total / n).powi(2)).sum::<f64>() / n).sqrt();
+    let (mut best, mut best_score, mut before) = (None, 0.0, 0.0);
+    for i in 0..logs.len() - SPEED_MIN_MERGES {
+        before += logs[i];
+        let (nb, ts) = ((i + 1) as f64, speeds[i + 1].0);
+        if i + 1 < SPEED_MIN_MERGES || ts - first < SPEED_MIN_SPAN || last - ts < SPEED_MIN_SPAN {
+            continue;
+        }
+        let delta = (total - before) / (n - nb) - before / nb;
+        let score = delta.abs() * (nb * (n - nb) / n).sqrt();
+        if score > best_score {
+            best = Some((ts, delta.exp()));
+            best_score = score;
+        }
+    }
+    debug!("Best speed step {:?}, score {:.2}, noise {:.2}", best, best_score, sd);
+    best.filter(|(_, f)| best_score > SPEED_MIN_SCORE * sd && f.ln().abs() > SPEED_MIN_FACTOR.ln())
+}
+
+/// Multiply a duration by a speed factor
+fn scale(dur: i64, factor: f64) -> i64 {
+    (dur as f64 * factor).round() as i64
+}
+
+/// Highest number of merges running at the same time, amongst the given (start, stop) times
+fn max_overlap(merges: &[(i64, i64)]) -> u16 {
+    let mut events: Vec<(i64, i32)> =
+        merges.iter().flat_map(|&(start, stop)| vec![(start, 1), (stop, -1)]).collect();
+    // Process stops before starts at the same timestamp, a merge starting when the previous one
+    // stops is sequential
+    events.sort_unstable();
+    let (mut cur, mut max) = (0, 1);
+    for (_, e) in events {
+        cur += e;
+        max = std::cmp::max(max, cur);
+    }
+    max as u16
+}
+
+/// Wall-clock time to run the given durations in order, each one starting as soon as one of the
+/// `jobs` slots is free
+///
+/// This ignores dependencies between packages, so it is optimistic when they are deep.
+fn schedule(durations: impl Iterator<Item = i64>, jobs: u16) -> i64 {
+    let mut slots = vec![0; jobs as usize];
+    for d in durations {
+        let slot = slots.iter_mut().min().unwrap();
+        *slot += d;
+    }
+    slots.into_iter().max().unwrap_or(0)
+}
+
+/// Merges separated by a longer pause belong to different sessions
+const SESSION_GAP: i64 = 600;
+
+/// Show how many merges ran concurrently, per session or per `--groupby` period
+///
+/// Sessions are runs of merges where each one starts less than SESSION_GAP after the previous ones
+/// finished. The busy time is the union of merge intervals; dividing the sum of merge times by it
+/// gives the average number of concurrent merges. The total covers all matching merges, even the
+/// ones in groups hidden by `--first` or `--last`.
+pub fn cmd_jobs(tw: &mut TabWriter<Stdout>,
+                args: &ArgMatches,
+                subargs: &ArgMatches,
+                st: &Styles)
+                -> Result<bool, Error> {
+    let timespan_opt = value_opt(subargs, "group", parse_timespan, ());
+    let (include, exclude) = pkg_terms(args, subargs)?;
+    let hist = new_hist(args.value_of("logfile").unwrap().into(),
+                        value_opt(args, "from", parse_date, st.date_offset),
+                        value_opt(args, "to", parse_date, st.date_offset),
+                        Show { merge: true, ..Show::default() },
+                        &include,
+                        &exclude,
+                        subargs.is_present("exact"))?;
+    let mut limit = Limit::from_args(subargs);
+    let mut merge_start: HashMap<String, i64> = HashMap::new();
+    let mut merges: Vec<(i64, i64)> = vec![];
+    let mut all: Vec<(i64, i64)> = vec![];
+    let mut max_end = i64::MIN;
+    let mut group_by = String::new();
+    let mut nextts = 0;
+    for p in hist {
+        match p {
+            Hist::MergeStart { ts, key, .. } => {
+                merge_start.insert(key, ts);
+            },
+            Hist::MergeStop { ts, ref key, .. } => {
+                let start_ts = match merge_start.remove(key) {
+                    Some(t) => t,
+                    None => continue,
+                };
+                let new_group = match timespan_opt {
+                    Some(_) => ts > nextts,
+                    None => merges.is_empty() || start_ts > max_end + SESSION_GAP,
+                };
+                if new_group {
+                    // Keep parsing after `--first` groups, the total covers all merges
+                    if !merges.is_empty() {
+                        let mut buf = vec![];
+                        cmd_jobs_group(&mut buf, st, &group_by, &merges)?;
+                        limit.push(tw, String::from_utf8(buf)?)?;
+                    }
+                    merges.clear();
+                    max_end = i64::MIN;
+                    match timespan_opt {
+                        Some(timespan) => {
+                            nextts = timespan.next(ts, st.date_offset);
+                            group_by = timespan.header(ts, st.date_offset).trim_end().to_string();
+                        },
+                        None => group_by = fmt_time(start_ts, st),
+                    }
+                }
+                merges.push((start_ts, ts));
+                max_end = std::cmp::max(max_end, ts);
+                all.push((start_ts, ts));
+            },
+            _ => unreachable!("Should only receive Hist::{{Start,Stop}}"),
+        }
+    }
+    if !merges.is_empty() {
+        let mut buf = vec![];
+        cmd_jobs_group(&mut buf, st, &group_by, &merges)?;
+        limit.push(tw, String::from_utf8(buf)?)?;
+    }
+    limit.flush(tw)?;
+    if !all.is_empty() {
+        cmd_jobs_group(tw, st, "Total", &all)?;
+    }
+    Ok(!all.is_empty())
+}
+
+/// Write one `cmd_jobs` row
+fn cmd_jobs_group(tw: &mut impl Write,
+                  st: &Styles,
+                  group_by: &str,
+                  merges: &[(i64, i64)])
+                  -> Result<(), Error> {
+    // Like Times::insert, count negative merge times but ignore their duration
+    let valid: Vec<(i64, i64)> =
+        merges.iter().copied().filter(|(start, stop)| stop >= start).collect();
+    let max = max_overlap(&valid);
+    let busy = busy_time(&valid);
+    let sum: i64 = valid.iter().map(|(start, stop)| stop - start).sum();
+    #[rustfmt::skip]
+    writeln!(tw, "{}\t{}{:>5}\t{:>4}\t{:>6.2}\t{}{:>10}\t{}{:>10}{}",
+             group_by,
+             st.cnt_p, merges.len(),
+             max,
+             sum as f64 / std::cmp::max(busy, 1) as f64,
+             st.dur_p, fmt_duration(st.dur_t, busy),
+             st.dur_p, fmt_duration(st.dur_t, sum),
+             st.dur_s)?;
+    Ok(())
+}
+
+/// Time during which at least one of the given (start, stop) merges was running
+///
+/// Merges that stopped before they started (clock changes) are ignored.
+fn busy_time(merges: &[(i64, i64)]) -> i64 {
+    let mut sorted: Vec<(i64, i64)> =
+        merges.iter().copied().filter(|(start, stop)| stop >= start).collect();
+    sorted.sort_unstable();
+    let (mut busy, mut end) = (0, i64::MIN);
+    for (start, stop) in sorted {
+        if start >= end {
+            busy += stop - start;
+            end = stop;
+        } else if stop > end {
+            busy += stop - end;
+            end = stop;
+        }
+    }
+    busy
+}
+
+/// Draw a Gantt chart of the merges in the last session, or after `--from`
+///
+/// Like cmd_list, we pair merge starts and stops, but we need to see all merges before drawing, to
+/// know the time scale. Bars are positioned relative to the first start, so parallel merges are
+/// easy to spot, as is the long merge that kept the session running alone.
+pub fn cmd_gantt(tw: &mut TabWriter<Stdout>,
+                 args: &ArgMatches,
+                 subargs: &ArgMatches,
+                 st: &Styles)
+                 -> Result<bool, Error> {
+    let (include, exclude) = pkg_terms(args, subargs)?;
+    let hist = new_hist(args.value_of("logfile").unwrap().into(),
+                        value_opt(args, "from", parse_date, st.date_offset),
+                        value_opt(args, "to", parse_date, st.date_offset),
+                        Show { merge: true, ..Show::default() },
+                        &include,
+                        &exclude,
+                        subargs.is_present("exact"))?;
+    let width = std::cmp::max(value(subargs, "width", parse_limit), 1) as i64;
+    let session = !args.is_present("from");
+    let world = Sets::from_args(args).world();
+    let mut merge_start: HashMap<String, i64> = HashMap::new();
+    let mut merges: Vec<(i64, i64, String, usize)> = vec![];
+    let mut end = i64::MIN;
+    for p in hist {
+        match p {
+            Hist::MergeStart { ts, key, .. } => {
+                merge_start.insert(key, ts);
+            },
+            Hist::MergeStop { ts, ref key, .. } => {
+                if let Some(start_ts) = merge_start.remove(key) {
+                    if session && start_ts > end.saturating_add(SESSION_GAP) {
+                        merges.clear();
+                    }
+                    // The clock may have gone backward during the merge
+                    end = std::cmp::max(end, std::cmp::max(start_ts, ts));
+                    merges.push((start_ts, ts, p.ebuild_version().to_string(), p.ebuild().len()));
+                }
+            },
+            _ => unreachable!("Should only receive Hist::{{Start,Stop}}"),
+        }
+    }
+    if merges.is_empty() {
         return Ok(false);
     }
+    merges.sort();
+    let first = merges[0].0;
+    let span = std::cmp::max(end - first, 1);
+    for (start, stop, pkg, ebuild_len) in &merges {
+        let pkg_p = if in_world(&world, &pkg[..*ebuild_len]) { &st.pkg_p } else { &st.pkgdep_p };
+        let a = std::cmp::min((start - first) * width / span, width - 1);
+        let b = std::cmp::min(std::cmp::max((stop - first) * width / span, a + 1), width);
+        #[rustfmt::skip]
+        writeln!(tw, "{}{}\t{}{:>8}{}\t|{:a$}{}{}{}{:c$}|",
+                 pkg_p, pkg,
+                 st.dur_p, fmt_duration(st.dur_t, stop - start), st.dur_s,
+                 "", st.dur_p, "#".repeat((b - a) as usize), st.dur_s, "",
+                 a = a as usize, c = (width - b) as usize)?;
+    }
+    #[rustfmt::skip]
+    writeln!(tw, "Total\t{}{:>8}{}\t {} - {}",
+             st.dur_p, fmt_duration(st.dur_t, end - first), st.dur_s,
+             fmt_time(first, st), fmt_time(end, st))?;
+    if let Some(file) = subargs.value_of("svg") {
+        let svg = fmt_gantt_svg(st, &merges, first, end);
+        std::fs::write(file, svg).with_context(|| format!("Cannot write {:?}", file))?;
+    }
+    Ok(true)
+}
+
+/// Render the `cmd_gantt` chart as a standalone SVG document
+fn fmt_gantt_svg(st: &Styles,
+                 merges: &[(i64, i64, String, usize)],
+                 first: i64,
+                 end: i64)
+                 -> String {
+    let (row_h, bar_w) = (20, 800);
+    let label_w = 8 * merges.iter().map(|m| m.2.len()).max().unwrap_or(0) as i64 + 10;
+    let span = std::cmp::max(end - first, 1);
+    let height = row_h * (merges.len() as i64 + 1);
+    let mut svg = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+                           font-family=\"monospace\" font-size=\"12\">\n",
+                          label_w + bar_w + 10,
+                          height);
+    for (i, (start, stop, pkg, _)) in merges.iter().enumerate() {
+        let y = row_h * i as i64;
+        let x = label_w + (start - first) * bar_w / span;
+        let w = std::cmp::max((stop - start) * bar_w / span, 1);
+        let dur = fmt_duration(st.dur_t, stop - start);
+        svg.push_str(&format!("<text x=\"0\" y=\"{}\">{}</text>\n\
+                               <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"#4a4\">\
+                               <title>{} {}</title></rect>\n",
+                              y + 14,
+                              fmt_xml_str(pkg),
+                              x,
+                              y + 2,
+                              w,
+                              row_h - 4,
+                              fmt_xml_str(pkg),
+                              dur));
+    }
+    svg.push_str(&format!("<text x=\"0\" y=\"{}\">{} - {} ({})</text>\n</svg>\n",
+                          height - 6,
+                          fmt_xml_str(&fmt_time(first, st)),
+                          fmt_xml_str(&fmt_time(end, st)),
+                          fmt_duration(st.dur_t, end - first)));
+    svg
+}
 
-    // Parse emerge log.
+/// Escape a string for use in XML text or attributes
+fn fmt_xml_str(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Measure how good predictions would have been
+///
+/// Replay merges chronologically, predicting each one (like cmd_predict does) using only the
+/// merges before it, and compare with the actual merge time. Packages are sorted by total error,
+/// because that's what affects the predicted ETA the most.
+pub fn cmd_accuracy(tw: &mut TabWriter<Stdout>,
+                    args: &ArgMatches,
+                    subargs: &ArgMatches,
+                    st: &Styles)
+                    -> Result<bool, Error> {
+    let lim = value(subargs, "limit", parse_limit);
+    let algo = value_t!(subargs, "predict-algo", PredictAlgo).unwrap();
+    let worst = value(subargs, "worst", parse_limit) as usize;
+    let (include, exclude) = pkg_terms(args, subargs)?;
     let hist = new_hist(args.value_of("logfile").unwrap().into(),
                         value_opt(args, "from", parse_date, st.date_offset),
                         value_opt(args, "to", parse_date, st.date_offset),
                         Show { merge: true, ..Show::default() },
-                        None,
-                        false)?;
-    let mut started: BTreeMap<(String, String), i64> = BTreeMap::new();
+                        &include,
+                        &exclude,
+                        subargs.is_present("exact"))?;
+    let mut vdb_slots = VdbSlots::new(args.value_of("pkgdir").unwrap());
+    let mut merge_start: HashMap<String, i64> = HashMap::new();
     let mut times: HashMap<String, Times> = HashMap::new();
+    let mut slot_times: HashMap<(String, String), Times> = HashMap::new();
+    // Per package: predicted merge count, total absolute error, total relative error
+    let mut errors: HashMap<String, (u64, i64, f64)> = HashMap::new();
+    let mut unknown = 0;
     for p in hist {
         match p {
-            // We're ignoring iter here (reducing the start->stop matching accuracy) because there's no iter in the pretend output.
-            Hist::MergeStart { ts, .. } => {
-                started.insert((p.ebuild().to_string(), p.version().to_string()), ts);
+            Hist::MergeStart { ts, key, .. } => {
+                merge_start.insert(key, ts);
             },
-            Hist::MergeStop { ts, .. } => {
-                let k = (p.ebuild().to_string(), p.version().to_string());
-                if let Some(start_ts) = started.remove(&k) {
-                    let timevec = times.entry(k.0).or_insert_with(Times::new);
-                    timevec.insert(ts - start_ts);
+            Hist::MergeStop { ts, ref key, ref slot, .. } => {
+                let dur = match merge_start.remove(key) {
+                    Some(start_ts) => ts - start_ts,
+                    None => continue,
+                };
+                let slot = slot.clone().or_else(|| vdb_slots.get(p.ebuild(), p.version()));
+                let slot_key = slot.map(|s| (p.ebuild().to_string(), s));
+                // Predict before recording this merge
+                let pred =
+                    slot_key.as_ref()
+                            .and_then(|k| slot_times.get(k))
+                            .and_then(|tv| tv.pred(lim, algo))
+                            .or_else(|| times.get(p.ebuild()).and_then(|tv| tv.pred(lim, algo)));
+                if dur > 0 {
+                    match pred {
+                        Some(pred) => {
+                            let (count, abs, rel) =
+                                errors.entry(p.ebuild().to_string()).or_default();
+                            *count += 1;
+                            *abs += (pred - dur).abs();
+                            *rel += (pred - dur).abs() as f64 / dur as f64;
+                        },
+                        None => unknown += 1,
+                    }
                 }
+                if let Some(k) = slot_key {
+                    slot_times.entry(k).or_default().insert(dur);
+                }
+                times.entry(p.ebuild().to_string()).or_default().insert(dur);
             },
-            _ => unreachable!("Should only receive Hist::{Start,Stop}"),
+            _ => unreachable!("Should only receive Hist::{{Start,Stop}}"),
         }
     }
-
-    // Parse list of pending merges (from stdin or from emerge log filtered by cms).
-    // We collect immediately to deal with type mismatches; it should be a small list anyway.
-    let pretend: Vec<Pretend> = if atty::is(atty::Stream::Stdin) {
-        started.iter()
-               .filter(|&(_, t)| *t > cms)
-               .map(|(&(ref e, ref v), _)| Pretend { ebuild: e.to_string(),
-                                                     version: v.to_string() })
-               .collect()
-    } else {
-        new_pretend(stdin(), "STDIN")
-    };
-
-    // Gather and print per-package and indivudual stats.
-    let mut totcount = 0;
-    let mut totunknown = 0;
-    let mut totpredict = 0;
-    let mut totelapsed = 0;
-    for Pretend { ebuild, version } in pretend {
-        // Find the elapsed time, if any (heuristic is that emerge process started before
-        // this merge finished, it's not failsafe but IMHO no worse than genlop).
-        let k = (ebuild, version);
-        let (elapsed, elapsed_fmt) = match started.remove(&k) {
-            Some(s) if s > cms => {
-                (now - s, format!(" - {}{}{}", st.dur_p, fmt_duration(st.dur_t, now - s), st.dur_s))
-            },
-            _ => (0, "".into()),
-        };
-        let (ebuild, version) = k;
-
-        // Find the predicted time and adjust counters
-        totcount += 1;
-        let pred_fmt = match times.get(&ebuild) {
-            Some(tv) => {
-                let pred = tv.pred(lim);
-                totpredict += pred;
-                if elapsed > 0 {
-                    totelapsed += elapsed;
-                    totpredict -= std::cmp::min(pred, elapsed);
-                }
-                fmt_duration(st.dur_t, pred)
-            },
-            None => {
-                totunknown += 1;
-                "?".into()
-            },
-        };
-
-        // Done
+    let mut rows: Vec<_> = errors.into_iter().collect();
+    rows.sort_by(|a, b| (b.1).1.cmp(&(a.1).1).then_with(|| a.0.cmp(&b.0)));
+    let (mut totcount, mut totabs, mut totrel) = (0, 0, 0.0);
+    for (_, (count, abs, rel)) in &rows {
+        totcount += count;
+        totabs += abs;
+        totrel += rel;
+    }
+    for (pkg, (count, abs, rel)) in rows.iter().take(worst) {
         #[rustfmt::skip]
-        writeln!(tw, "{}{}-{}\t{}{:>9}{}{}",
-                 st.pkg_p, ebuild, version,
-                 st.dur_p, pred_fmt,
-                 st.dur_s, elapsed_fmt)?;
+        writeln!(tw, "{}{}\t{}{:>5}\t{}{:>8}\t{}{:>7.1}%{}",
+                 st.pkg_p, pkg,
+                 st.cnt_p, count,
+                 st.dur_p, fmt_duration(st.dur_t, abs / *count as i64),
+                 st.cnt_p, rel * 100.0 / *count as f64,
+                 st.cnt_s)?;
     }
     if totcount > 0 {
         #[rustfmt::skip]
-        writeln!(tw, "Estimate for {}{}{} ebuilds ({}{}{} unknown, {}{}{} elapsed)\t{}{:>9}{} @ {}{}{}",
-                 st.cnt_p, totcount, st.cnt_s,
-                 st.cnt_p, totunknown, st.cnt_s,
-                 st.dur_p, fmt_duration(st.dur_t, totelapsed), st.dur_s,
-                 st.dur_p, fmt_duration(st.dur_t, totpredict), st.dur_s,
-                 st.dur_p, fmt_time(now + totpredict, st), st.dur_s)?;
-    } else {
-        writeln!(tw, "No pretended merge found")?;
+        writeln!(tw, "Total\t{}{:>5}\t{}{:>8}\t{}{:>7.1}%{}",
+                 st.cnt_p, totcount,
+                 st.dur_p, fmt_duration(st.dur_t, totabs / totcount as i64),
+                 st.cnt_p, totrel * 100.0 / totcount as f64,
+                 st.cnt_s)?;
     }
+    writeln!(tw, "Unknown\t{}{:>5}{}", st.cnt_p, unknown, st.cnt_s)?;
     Ok(totcount > 0)
 }
 
@@ -361,13 +1850,16 @@ pub fn cmd_complete(subargs: &ArgMatches) -> Result<bool, Error> {
         Some("fish") => clap::Shell::Fish,
         o => bail!("Unsupported shell {:?}", o),
     };
-    cli::build_cli_nocomplete().gen_completions_to("emlop", shell, &mut std::io::stdout());
+    cli::build_cli_nocomplete(false).gen_completions_to("emlop", shell, &mut std::io::stdout());
     Ok(true)
 }
 
 
 #[cfg(test)]
 mod tests {
+    use super::{busy_time, fmt_progress, max_overlap, remaining, scale, schedule, speed_ratio,
+                speed_step, Times};
+    use crate::{dataset::Dataset, DurationStyle, PredictAlgo, Styles};
     use assert_cmd::Command;
     use escargot::CargoBuild;
     use lazy_static::lazy_static;
@@ -418,6 +1910,90 @@ mod tests {
               2018-03-12 10:35:22        14 >>> x11-apps/xlsclients-1.1.4\n\
               2018-03-12 11:03:53        16 >>> kde-frameworks/kxmlrpcclient-5.44.0\n",
              0),
+            // Multiple search terms and exclusions
+            (&["-F", "test/emerge.10000.log", "l", "client", "kactivities", "-x", "chromium", "-x", "stats"],
+             "2018-02-04 04:55:19     35:46 >>> mail-client/thunderbird-52.6.0\n\
+              2018-02-04 05:42:48     47:29 >>> www-client/firefox-58.0.1\n\
+              2018-02-09 11:04:59     47:58 >>> mail-client/thunderbird-52.6.0-r1\n\
+              2018-02-12 10:14:11        31 >>> kde-frameworks/kxmlrpcclient-5.43.0\n\
+              2018-02-12 10:35:23        46 >>> kde-frameworks/kactivities-5.43.0\n\
+              2018-02-22 13:32:53        44 >>> www-client/links-2.14-r1\n\
+              2018-02-28 09:14:37      6:02 >>> www-client/falkon-3.0.0\n\
+              2018-03-12 10:35:22        14 >>> x11-apps/xlsclients-1.1.4\n\
+              2018-03-12 11:03:53        16 >>> kde-frameworks/kxmlrpcclient-5.44.0\n\
+              2018-03-12 11:04:28        35 >>> kde-frameworks/kactivities-5.44.0\n",
+             0),
+            // Search terms from a file
+            (&["-F", "test/emerge.10000.log", "l", "--package-file", "test/packages.txt", "-e"],
+             "2018-02-04 04:55:19     35:46 >>> mail-client/thunderbird-52.6.0\n\
+              2018-02-09 11:04:59     47:58 >>> mail-client/thunderbird-52.6.0-r1\n\
+              2018-02-16 04:41:39   6:03:14 >>> www-client/chromium-64.0.3282.140\n\
+              2018-02-19 17:35:41   7:56:03 >>> www-client/chromium-64.0.3282.167\n\
+              2018-02-22 13:32:53        44 >>> www-client/links-2.14-r1\n\
+              2018-03-06 04:19:52   7:42:07 >>> www-client/chromium-64.0.3282.186\n",
+             0),
+            // Package sets
+            (&["-F", "test/emerge.10000.log", "--worldfile", "test/portage/world", "--setsdir", "test/portage/sets", "l", "@world", "@browsers", "-x", "chromium"],
+             "2018-02-04 05:42:48     47:29 >>> www-client/firefox-58.0.1\n\
+              2018-02-12 10:35:23        46 >>> kde-frameworks/kactivities-5.43.0\n\
+              2018-02-22 13:32:53        44 >>> www-client/links-2.14-r1\n\
+              2018-03-12 11:04:28        35 >>> kde-frameworks/kactivities-5.44.0\n",
+             0),
+            // World file members are highlighted
+            (&["-F", "test/emerge.10000.log", "--worldfile", "test/portage/world", "l", "kactivities", "--color", "y", "--duration", "s"],
+             "2018-02-12 10:35:23 \x1b[1;35m       46 \x1b[1;32mkde-frameworks/kactivities-5.43.0\x1b[0m\n\
+              2018-02-12 10:39:10 \x1b[1;35m       41 \x1b[32mkde-frameworks/kactivities-stats-5.43.0\x1b[0m\n\
+              2018-03-12 11:04:28 \x1b[1;35m       35 \x1b[1;32mkde-frameworks/kactivities-5.44.0\x1b[0m\n\
+              2018-03-12 11:18:00 \x1b[1;35m       24 \x1b[32mkde-frameworks/kactivities-stats-5.44.0\x1b[0m\n",
+             0),
+            // Filter by duration
+            (&["-F", "test/emerge.10000.log", "l", "--min-duration", "2:00:00"],
+             "2018-02-04 04:19:33   3:04:21 >>> dev-qt/qtwebengine-5.9.4\n\
+              2018-02-04 22:59:07   2:14:06 >>> app-office/libreoffice-6.0.0.3\n\
+              2018-02-12 14:48:14   2:48:31 >>> app-office/libreoffice-6.0.1.1\n\
+              2018-02-16 04:41:39   6:03:14 >>> www-client/chromium-64.0.3282.140\n\
+              2018-02-19 17:35:41   7:56:03 >>> www-client/chromium-64.0.3282.167\n\
+              2018-02-22 17:39:17   3:53:46 >>> dev-qt/qtwebengine-5.9.4\n\
+              2018-03-01 22:08:47   2:13:00 >>> app-office/libreoffice-6.0.2.1\n\
+              2018-03-06 04:19:52   7:42:07 >>> www-client/chromium-64.0.3282.186\n",
+             0),
+            (&["-F", "test/emerge.10000.log", "l", "-sa", "--min-duration", "4:00", "--max-duration", "300", "--from", "2018-03-01"],
+             "2018-03-08 23:40:57      4:07 >>> kde-apps/kleopatra-17.12.3\n\
+              2018-03-09 00:32:11      4:31 >>> kde-apps/akregator-17.12.3\n",
+             0),
+            // Limit and reverse output
+            (&["-F", "test/emerge.10000.log", "l", "--first", "2", "-sa"],
+             "2018-02-02 22:09:08         ? >>> sys-apps/man-pages-4.14\n\
+              2018-02-03 23:00:13      1:09 Sync\n",
+             0),
+            (&["-F", "test/emerge.10000.log", "l", "--last", "3", "-r"],
+             "2018-03-12 11:24:43      1:13 >>> kde-frameworks/baloo-5.44.0\n\
+              2018-03-12 11:23:30        18 >>> net-misc/kafka-bin-0.11.0.2-r1\n\
+              2018-03-12 11:23:12        34 >>> dev-java/icedtea-bin-3.7.0\n",
+             0),
+            (&["-F", "test/emerge.10000.log", "l", "client", "--first", "2", "--reverse"],
+             "2018-02-04 05:42:48     47:29 >>> www-client/firefox-58.0.1\n\
+              2018-02-04 04:55:19     35:46 >>> mail-client/thunderbird-52.6.0\n",
+             0),
+            // Start and end times
+            (&["-F", "test/emerge.10000.log", "l", "-sa", "--time", "both", "--from", "2018-03-07 10:42:00", "--to", "2018-03-07 14:00:00"],
+             "2018-03-07 10:42:56 - 2018-03-07 10:43:10        14 >>> sys-apps/the_silver_searcher-2.0.0\n\
+              2018-03-07 11:36:27 - 2018-03-07 11:37:05        38 Sync\n\
+              2018-03-07 12:49:07 - 2018-03-07 12:49:09         2 <<< sys-apps/util-linux-2.30.2\n\
+              2018-03-07 12:48:12 - 2018-03-07 12:49:13      1:01 >>> sys-apps/util-linux-2.30.2-r1\n\
+              2018-03-07 13:55:29 - 2018-03-07 13:56:09        40 Sync\n\
+              2018-03-07 13:59:36 - 2018-03-07 13:59:38         2 <<< dev-libs/nspr-4.17\n\
+              2018-03-07 13:59:17 - 2018-03-07 13:59:41        24 >>> dev-libs/nspr-4.18\n",
+             0),
+            (&["-F", "test/emerge.10000.log", "l", "--time", "start", "--first", "2"],
+             "                  ?         ? >>> sys-apps/man-pages-4.14\n\
+              2018-02-03 23:11:35        12 >>> sys-process/parallel-20171222\n",
+             0),
+            // Replaced version is found even when unmerges aren't displayed
+            (&["-F", "test/emerge.10000.log", "l", "-c", "-e", "postgresql", "--from", "2018-03-01"],
+             "2018-03-02 16:39:46      2:58 U >>> dev-db/postgresql-10.3 [10.2]\n\
+              2018-03-02 16:43:00      3:14 U >>> dev-db/postgresql-9.5.12 [9.5.11]\n",
+             0),
             // Check output when duration isn't known
             (&["-F", "test/emerge.10000.log", "l", "-s", "m", "mlt", "-e", "--from", "2018-02-18 12:37:00"],
              "2018-02-18 12:37:09         ? >>> media-libs/mlt-6.4.1-r6\n\
@@ -438,7 +2014,25 @@ mod tests {
               2018-03-07 13:56:09        40 Sync\n\
               2018-03-07 13:59:38         2 <<< dev-libs/nspr-4.17\n\
               2018-03-07 13:59:41        24 >>> dev-libs/nspr-4.18\n",
-             0)
+             0),
+            // Check upgrade/downgrade/reinstall markers, and the alignment of other events
+            (&["-F", "test/emerge.10000.log", "l", "-c", "-smu", "-e", "postgresql"],
+             "2018-02-12 09:29:57        17   <<< dev-db/postgresql-10.1\n\
+              2018-02-12 09:30:13      2:58 U >>> dev-db/postgresql-10.2 [10.1]\n\
+              2018-02-12 09:32:46        15   <<< dev-db/postgresql-9.5.10\n\
+              2018-02-12 09:33:03      2:50 U >>> dev-db/postgresql-9.5.11 [9.5.10]\n\
+              2018-03-02 16:39:30        16   <<< dev-db/postgresql-10.2\n\
+              2018-03-02 16:39:46      2:58 U >>> dev-db/postgresql-10.3 [10.2]\n\
+              2018-03-02 16:42:44        16   <<< dev-db/postgresql-9.5.11\n\
+              2018-03-02 16:43:00      3:14 U >>> dev-db/postgresql-9.5.12 [9.5.11]\n",
+             0),
+            (&["-F", "test/emerge.10000.log", "l", "-c", "-sms", "-e", "mlt", "--from", "2018-02-27 14:30", "--to", "2018-02-27 17:00"],
+             "2018-02-27 15:10:05        43 R >>> media-libs/mlt-6.4.1-r6\n\
+              2018-02-27 15:51:43        21   Sync\n\
+              2018-02-27 16:24:58        20   Sync\n\
+              2018-02-27 16:48:40        39 R >>> media-libs/mlt-6.4.1-r6\n\
+              2018-02-27 16:52:44        16   Sync\n",
+             0),
         ];
         for (a, o, e) in t {
             emlop().args(a).assert().code(e).stdout(o);
@@ -508,20 +2102,33 @@ mod tests {
         let t = vec![// Check garbage input
                      ("blah blah\n", format!("No pretended merge found\n"), 2),
                      // Check all-unknowns
-                     ("[ebuild   R   ~] dev-lang/unknown-1.42\n",
-                      format!("dev-lang/unknown-1.42                                  ?\n\
-                               Estimate for 1 ebuilds (1 unknown, 0 elapsed)          0 @ {}\n",
-                              ts(0)),
+                     ("[ebuild   R   ~] dev-unknown/unknown-1.42\n",
+                      format!("dev-unknown/unknown-1.42                               ?\n\
+                               Estimate for 1 ebuilds (1 unknown, 0 elapsed)          0 @ {}\n\
+                               Estimate range                                         0 - 0 @ {} - {}\n",
+                              ts(0), ts(0), ts(0)),
                       0),
                      // Check that unknown ebuild don't wreck alignment. Remember that times are {:>9}
                      ("[ebuild   R   ~] dev-qt/qtcore-5.9.4-r2\n\
-                       [ebuild   R   ~] dev-lang/unknown-1.42\n\
+                       [ebuild   R   ~] dev-unknown/unknown-1.42\n\
                        [ebuild   R   ~] dev-qt/qtgui-5.9.4-r3\n",
-                      format!("dev-qt/qtcore-5.9.4-r2                              3:44\n\
-                               dev-lang/unknown-1.42                                  ?\n\
-                               dev-qt/qtgui-5.9.4-r3                               4:36\n\
-                               Estimate for 3 ebuilds (1 unknown, 0 elapsed)       8:20 @ {}\n",
-                              ts(8 * 60 + 20)),
+                      format!("dev-qt/qtcore-5.9.4-r2                              3:44  3:40 - 3:49\n\
+                               dev-unknown/unknown-1.42                               ?\n\
+                               dev-qt/qtgui-5.9.4-r3                               4:36  4:22 - 4:54\n\
+                               Estimate for 3 ebuilds (1 unknown, 0 elapsed)       8:20 @ {}\n\
+                               Estimate range                                      8:02 - 8:43 @ {} - {}\n",
+                              ts(8 * 60 + 20), ts(8 * 60 + 2), ts(8 * 60 + 43)),
+                      0),
+                     // Check estimates for never-merged packages
+                     ("[ebuild   R   ~] dev-lang/unknown-1.42\n\
+                       [ebuild  N     ] www-client/firefox-bin-60\n\
+                       [ebuild  N     ] foo-bar/baz-1\n",
+                      format!("dev-lang/unknown-1.42                                           ~1:15  19 - 19:42 (dev-lang/* median)\n\
+                               www-client/firefox-bin-60                                      ~47:29  47:29 - 47:29 (like www-client/firefox)\n\
+                               foo-bar/baz-1                                                       ?\n\
+                               Estimate for 3 ebuilds (1 unknown, 2 estimated, 0 elapsed)      48:44 @ {}\n\
+                               Estimate range                                                  47:48 - 1:07:11 @ {} - {}\n",
+                              ts(48 * 60 + 44), ts(47 * 60 + 48), ts(67 * 60 + 11)),
                       0),];
         for (i, o, e) in t {
             emlop().args(&["-F", "test/emerge.10000.log", "p", "--date", "unix"])
@@ -530,6 +2137,154 @@ mod tests {
                    .code(e)
                    .stdout(o);
         }
+        let o = format!("foo-bar/baz-1                                                  ~10:00  10:00 - 10:00 (default)\n\
+                         Estimate for 1 ebuilds (0 unknown, 1 estimated, 0 elapsed)      10:00 @ {}\n\
+                         Estimate range                                                  10:00 - 10:00 @ {} - {}\n",
+                        ts(10 * 60), ts(10 * 60), ts(10 * 60));
+        emlop().args(&["-F",
+                       "test/emerge.10000.log",
+                       "p",
+                       "--date",
+                       "unix",
+                       "--default-time",
+                       "10:00"])
+               .write_stdin("[ebuild  N     ] foo-bar/baz-1\n")
+               .assert()
+               .success()
+               .stdout(o);
+    }
+
+    #[test]
+    fn export() {
+        #[rustfmt::skip]
+        let t: Vec<(&[&str], &str, i32)> = vec![
+            (&["-F","test/emerge.10000.log","export","--limit","3","postgres","qtcore"],
+             "package,slot,count,times\n\
+              app-eselect/eselect-postgresql,0,1,23\n\
+              dev-db/postgresql,10,2,178 178\n\
+              dev-db/postgresql,9.5,2,170 194\n\
+              dev-qt/qtcore,4,1,218\n\
+              dev-qt/qtcore,5,3,223 227 230\n",
+             0),
+            (&["-F","test/emerge.10000.log","export","notfound"],
+             "package,slot,count,times\n",
+             2),
+        ];
+        for (a, o, e) in t {
+            emlop().args(a).assert().code(e).stdout(o);
+        }
+    }
+
+    /// Ignored by default: depends on there being no currently running emerge.
+    #[ignore]
+    #[test]
+    fn predict_imported() {
+        let _cache_cargo_build = emlop();
+        let dir = std::env::temp_dir().join(format!("emlop-test-{}", std::process::id()));
+        let exported = dir.join("exported.csv");
+        let timesfile = dir.join("times.csv");
+        std::fs::create_dir_all(&dir).unwrap();
+        let out = emlop().args(&["-F", "test/emerge.10000.log", "e", "qtcore"]).output().unwrap();
+        std::fs::write(&exported, out.stdout).unwrap();
+        emlop().args(&["--timesfile",
+                       timesfile.to_str().unwrap(),
+                       "im",
+                       exported.to_str().unwrap()])
+               .assert()
+               .success();
+        let o = format!("dev-qt/qtcore-5.9.4-r2                                          ~3:44  3:40 - 3:49 (imported)\n\
+                         Estimate for 1 ebuilds (0 unknown, 1 estimated, 0 elapsed)       3:44 @ {}\n\
+                         Estimate range                                                   3:40 - 3:49 @ {} - {}\n",
+                        ts(3 * 60 + 44), ts(3 * 60 + 40), ts(3 * 60 + 49));
+        emlop().args(&["-F",
+                       "test/emerge.negtime.log",
+                       "--timesfile",
+                       timesfile.to_str().unwrap(),
+                       "p",
+                       "--date",
+                       "unix"])
+               .write_stdin("[ebuild   R   ~] dev-qt/qtcore-5.9.4-r2\n")
+               .assert()
+               .success()
+               .stdout(o);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Ignored by default: depends on there being no currently running emerge.
+    #[ignore]
+    #[test]
+    fn predict_jobs() {
+        let _cache_cargo_build = emlop();
+        let i = "[ebuild   R   ~] dev-qt/qtcore-5.9.4-r2\n\
+                 [ebuild   R   ~] kde-plasma/plasma-desktop-5.12.4\n\
+                 [ebuild   R   ~] dev-qt/qtgui-5.9.4-r3\n";
+        let o = format!("dev-qt/qtcore-5.9.4-r2                                      3:44  3:40 - 3:49\n\
+                         kde-plasma/plasma-desktop-5.12.4                           10:01  7:22 - 11:52\n\
+                         dev-qt/qtgui-5.9.4-r3                                       4:36  4:22 - 4:54\n\
+                         Estimate for 3 ebuilds (0 unknown, 0 elapsed, 2 jobs)      10:01 @ {}\n\
+                         Estimate range                                              8:02 - 11:52 @ {} - {}\n",
+                        ts(10 * 60 + 1), ts(8 * 60 + 2), ts(11 * 60 + 52));
+        emlop().args(&["-F", "test/emerge.10000.log", "p", "--date", "unix", "--jobs", "2"])
+               .write_stdin(i)
+               .assert()
+               .success()
+               .stdout(o);
+    }
+
+    #[test]
+    fn progress() {
+        let st = Styles::new(false, DurationStyle::HMS, "unix".parse().unwrap(), true);
+        assert_eq!(fmt_progress(&st, 1000, 100, 0), "");
+        assert_eq!(fmt_progress(&st, 1000, 100, 25), ", 25% done @ 1075");
+        assert_eq!(fmt_progress(&st, 1000, 100, 100), ", 100% done @ 1000");
+        assert_eq!(fmt_progress(&st, 1000, 100, 190), ", overdue by 1:30");
+        assert_eq!(remaining(0, 100, (80, 120)), (100, 80, 120));
+        assert_eq!(remaining(90, 100, (80, 120)), (10, 0, 30));
+        assert_eq!(remaining(110, 100, (80, 120)), (20, 0, 20));
+        assert_eq!(remaining(190, 100, (80, 120)), (20, 0, 20));
+        assert_eq!(remaining(36000, 3600, (3000, 4200)), (600, 0, 600));
+    }
+
+    #[test]
+    fn speed() {
+        let day = 24 * 3600;
+        // Alternating fast and slow merges, then everything becomes twice as fast
+        let mut speeds: Vec<(i64, f64)> =
+            (0..200).map(|i| (i * day / 5, if i % 2 == 0 { 0.8 } else { 1.2 })).collect();
+        assert_eq!(speed_step(&speeds), None);
+        speeds.iter_mut().skip(120).for_each(|s| s.1 /= 2.0);
+        let (ts, f) = speed_step(&speeds).unwrap();
+        assert_eq!(ts, 120 * day / 5);
+        assert!((f - 0.5).abs() < 0.01, "{}", f);
+        // Too recent to be trusted
+        assert_eq!(speed_step(&speeds[..130]), None);
+        let mut old = Dataset::default();
+        let mut new: HashMap<String, Times> = HashMap::new();
+        for (i, pkg) in ["a", "b", "c", "d", "e", "f"].iter().enumerate() {
+            old.insert(pkg, "", 1, &[100 * (i as i64 + 1)]);
+            new.entry(pkg.to_string()).or_default().insert(50 * (i as i64 + 1) + i as i64);
+        }
+        assert_eq!(speed_ratio(&new, &old, 10, PredictAlgo::Mean), Some(0.5075));
+        new.remove("a");
+        new.remove("b");
+        assert_eq!(speed_ratio(&new, &old, 10, PredictAlgo::Mean), None);
+        assert_eq!(scale(100, 0.505), 51);
+    }
+
+    #[test]
+    fn schedule_jobs() {
+        assert_eq!(schedule(vec![].into_iter(), 2), 0);
+        assert_eq!(schedule(vec![3, 10, 4].into_iter(), 1), 17);
+        assert_eq!(schedule(vec![3, 10, 4].into_iter(), 2), 10);
+        assert_eq!(schedule(vec![10, 3, 4, 5].into_iter(), 2), 12);
+        assert_eq!(schedule(vec![3, 10, 4].into_iter(), 8), 10);
+        assert_eq!(max_overlap(&[]), 1);
+        assert_eq!(max_overlap(&[(0, 10), (10, 20), (20, 30)]), 1);
+        assert_eq!(max_overlap(&[(0, 10), (5, 20), (6, 7), (15, 30)]), 3);
+        assert_eq!(busy_time(&[]), 0);
+        assert_eq!(busy_time(&[(0, 10), (10, 20), (30, 40)]), 30);
+        assert_eq!(busy_time(&[(15, 30), (0, 10), (5, 20), (6, 7)]), 30);
+        assert_eq!(busy_time(&[(0, 10), (50, 20)]), 10);
     }
 
     #[test]
@@ -566,6 +2321,78 @@ mod tests {
             (&["-F","test/emerge.10000.log","s","--from","2018-02-03T23:11:47","--to","2018-02-04","notfound","-sa"],
              "",
              2),
+            (&["-F","test/emerge.10000.log","s","-sa","--min-duration","1:00:00"],
+             "app-office/libreoffice      3     7:15:37   2:25:12      0         0         ?\n\
+              dev-qt/qtwebengine          2     6:58:07   3:29:03      0         0         ?\n\
+              www-client/chromium         3    21:41:24   7:13:48      0         0         ?\n\
+              Total                       8    35:55:08   4:29:23      0         0         ?\n",
+             0),
+            (&["-F","test/emerge.10000.log","--pkgdir","test/vdb","s","--details","-e","postgresql","unlogged"],
+             "app-misc/unlogged\n\
+              \x20\x20Merges:        0, total 0, predicted ?\n\
+              \x20\x20Unmerges:      0, total 0, predicted ?\n\
+              \x20\x20Installed:     1.0, merge count 0, average ?\n\
+              \x20\x20\x20\x20SLOT:        0\n\
+              \x20\x20\x20\x20repository:  gentoo\n\
+              \x20\x20\x20\x20BUILD_TIME:  2018-03-02 14:13:20\n\
+              \x20\x20\x20\x20SIZE:        12.1 KiB\n\
+              \x20\x20\x20\x20CHOST:       x86_64-pc-linux-gnu\n\
+              \x20\x20\x20\x20CFLAGS:      -O2 -pipe -march=native\n\
+              \x20\x20\x20\x20USE:         amd64 elibc_glibc\n\
+              dev-db/postgresql:10\n\
+              \x20\x20Merges:        2, total 5:56, predicted 2:58\n\
+              \x20\x20Unmerges:      2, total 33, predicted 16\n\
+              \x20\x20Installed:     10.3, merge count 1, average 2:58\n\
+              \x20\x20\x20\x20SLOT:        10\n\
+              \x20\x20\x20\x20repository:  gentoo\n\
+              \x20\x20\x20\x20BUILD_TIME:  2018-03-02 16:39:46\n\
+              \x20\x20\x20\x20SIZE:        51.8 MiB\n\
+              \x20\x20\x20\x20CHOST:       x86_64-pc-linux-gnu\n\
+              \x20\x20\x20\x20CFLAGS:      -O2 -pipe -march=native\n\
+              \x20\x20\x20\x20USE:         amd64 icu nls pam readline ssl zlib\n\
+              dev-db/postgresql:9.5\n\
+              \x20\x20Merges:        2, total 6:04, predicted 3:02\n\
+              \x20\x20Unmerges:      2, total 31, predicted 15\n\
+              \x20\x20Installed:     9.5.11, merge count 1, average 2:50\n\
+              \x20\x20\x20\x20SLOT:        9.5\n\
+              \x20\x20\x20\x20repository:  gentoo\n\
+              \x20\x20\x20\x20BUILD_TIME:  2018-02-12 09:33:03\n\
+              \x20\x20\x20\x20SIZE:        41.2 MiB\n\
+              \x20\x20\x20\x20CHOST:       x86_64-pc-linux-gnu\n\
+              \x20\x20\x20\x20CFLAGS:      -O2 -pipe -march=native\n\
+              \x20\x20\x20\x20USE:         amd64 nls pam readline ssl zlib\n",
+             0),
+            (&["-F","test/emerge.10000.log","s","--duration","s","-e","plasma-desktop","--predict-algo","mean"],
+             "kde-plasma/plasma-desktop      5        3006       601      5        12         2\n",
+             0),
+            (&["-F","test/emerge.10000.log","s","--duration","s","-e","plasma-desktop","--predict-algo","median"],
+             "kde-plasma/plasma-desktop      5        3006       671      5        12         2\n",
+             0),
+            (&["-F","test/emerge.10000.log","s","--duration","s","-e","plasma-desktop","--predict-algo","ewma"],
+             "kde-plasma/plasma-desktop      5        3006       596      5        12         2\n",
+             0),
+            (&["-F","test/emerge.10000.log","s","--duration","s","-e","plasma-desktop","--predict-algo","trimmed"],
+             "kde-plasma/plasma-desktop      5        3006       635      5        12         2\n",
+             0),
+            (&["-F","test/emerge.10000.log","s","--limit","0","-e","plasma-desktop"],
+             "kde-plasma/plasma-desktop      5       50:06         ?      5        12         ?\n",
+             0),
+            (&["-F","test/emerge.10000.log","s","--versions","chromium"],
+             "www-client/chromium-64.0.3282.140      1     6:03:14   6:03:14         \n\
+              www-client/chromium-64.0.3282.167      1     7:56:03   7:56:03   +31.1%\n\
+              www-client/chromium-64.0.3282.186      1     7:42:07   7:42:07    -2.9%\n",
+             0),
+            (&["-F","test/emerge.10000.log","s","--versions","-e","gentoo-sources","--to","2018-03-02"],
+             "sys-kernel/gentoo-sources-4.14.17        1        1:21      1:21         \n\
+              sys-kernel/gentoo-sources-4.15.1         1        1:35      1:35   +17.3%\n\
+              sys-kernel/gentoo-sources-4.15.2         1        1:37      1:37    +2.1%\n\
+              sys-kernel/gentoo-sources-4.15.3         1        1:20      1:20   -17.5%\n\
+              sys-kernel/gentoo-sources-4.15.4         1        1:58      1:58   +47.5%\n\
+              sys-kernel/gentoo-sources-4.15.5         1        1:17      1:17   -34.7%\n\
+              sys-kernel/gentoo-sources-4.15.6         1        1:19      1:19    +2.6%\n\
+              sys-kernel/gentoo-sources-4.15.7         1        1:15      1:15    -5.1%\n\
+              sys-kernel/gentoo-sources-4.15.7-r1      1        1:22      1:22    +9.3%\n",
+             0),
         ];
         for (a, o, e) in t {
             emlop().args(a).assert().code(e).stdout(o);
@@ -580,33 +2407,72 @@ mod tests {
         #[rustfmt::skip]
         let t: Vec<(&[&str],&str)> = vec![
             (&["-F","test/emerge.10000.log","s","--duration","s","-sp","gentoo-sources","-gy"],
-             "2018 sys-kernel/gentoo-sources     10         904        90     11       200        16\n"),
+             "2018 sys-kernel/gentoo-sources:?              0           0         ?      2        27        13\n\
+              2018 sys-kernel/gentoo-sources:4.14.17        1          81        81      1        39        39\n\
+              2018 sys-kernel/gentoo-sources:4.15.1         1          95        95      1        12        12\n\
+              2018 sys-kernel/gentoo-sources:4.15.2         1          97        97      1        12        12\n\
+              2018 sys-kernel/gentoo-sources:4.15.3         1          80        80      1        13        13\n\
+              2018 sys-kernel/gentoo-sources:4.15.4         1         118       118      1        24        24\n\
+              2018 sys-kernel/gentoo-sources:4.15.5         1          77        77      1        22        22\n\
+              2018 sys-kernel/gentoo-sources:4.15.6         1          79        79      1        15        15\n\
+              2018 sys-kernel/gentoo-sources:4.15.7         1          75        75      1        23        23\n\
+              2018 sys-kernel/gentoo-sources:4.15.7-r1      1          82        82      1        13        13\n\
+              2018 sys-kernel/gentoo-sources:4.15.9         1         120       120      0         0         ?\n"),
             (&["-F","test/emerge.10000.log","s","--duration","s","-sp","gentoo-sources","-gm"],
-             "2018-02 sys-kernel/gentoo-sources      8         702        87      8       149        18\n\
-              2018-03 sys-kernel/gentoo-sources      2         202       101      3        51        17\n"),
+             "2018-02 sys-kernel/gentoo-sources:?              0           0         ?      2        27        13\n\
+              2018-02 sys-kernel/gentoo-sources:4.14.17        1          81        81      1        39        39\n\
+              2018-02 sys-kernel/gentoo-sources:4.15.1         1          95        95      1        12        12\n\
+              2018-02 sys-kernel/gentoo-sources:4.15.2         1          97        97      1        12        12\n\
+              2018-02 sys-kernel/gentoo-sources:4.15.3         1          80        80      1        13        13\n\
+              2018-02 sys-kernel/gentoo-sources:4.15.4         1         118       118      1        24        24\n\
+              2018-02 sys-kernel/gentoo-sources:4.15.5         1          77        77      1        22        22\n\
+              2018-02 sys-kernel/gentoo-sources:4.15.6         1          79        79      0         0         ?\n\
+              2018-02 sys-kernel/gentoo-sources:4.15.7         1          75        75      0         0         ?\n\
+              2018-03 sys-kernel/gentoo-sources:4.15.6         0           0         ?      1        15        15\n\
+              2018-03 sys-kernel/gentoo-sources:4.15.7         0           0         ?      1        23        23\n\
+              2018-03 sys-kernel/gentoo-sources:4.15.7-r1      1          82        82      1        13        13\n\
+              2018-03 sys-kernel/gentoo-sources:4.15.9         1         120       120      0         0         ?\n"),
             (&["-F","test/emerge.10000.log","s","--duration","s","-sp","gentoo-sources","-gw"],
-             "2018-05 sys-kernel/gentoo-sources      1          81        81      0         0         ?\n\
-              2018-06 sys-kernel/gentoo-sources      2         192        96      3        66        22\n\
-              2018-07 sys-kernel/gentoo-sources      2         198        99      0         0         ?\n\
-              2018-08 sys-kernel/gentoo-sources      1          77        77      3        37        12\n\
-              2018-09 sys-kernel/gentoo-sources      3         236        78      3        61        20\n\
-              2018-10 sys-kernel/gentoo-sources      0           0         ?      1        23        23\n\
-              2018-11 sys-kernel/gentoo-sources      1         120       120      1        13        13\n"),
+             "2018-05 sys-kernel/gentoo-sources                1          81        81      0         0         ?\n\
+              2018-06 sys-kernel/gentoo-sources:?              0           0         ?      2        27        13\n\
+              2018-06 sys-kernel/gentoo-sources:4.14.17        0           0         ?      1        39        39\n\
+              2018-06 sys-kernel/gentoo-sources:4.15.1         1          95        95      0         0         ?\n\
+              2018-06 sys-kernel/gentoo-sources:4.15.2         1          97        97      0         0         ?\n\
+              2018-07 sys-kernel/gentoo-sources:4.15.3         1          80        80      0         0         ?\n\
+              2018-07 sys-kernel/gentoo-sources:4.15.4         1         118       118      0         0         ?\n\
+              2018-08 sys-kernel/gentoo-sources:4.15.1         0           0         ?      1        12        12\n\
+              2018-08 sys-kernel/gentoo-sources:4.15.2         0           0         ?      1        12        12\n\
+              2018-08 sys-kernel/gentoo-sources:4.15.3         0           0         ?      1        13        13\n\
+              2018-08 sys-kernel/gentoo-sources:4.15.5         1          77        77      0         0         ?\n\
+              2018-09 sys-kernel/gentoo-sources:4.15.4         0           0         ?      1        24        24\n\
+              2018-09 sys-kernel/gentoo-sources:4.15.5         0           0         ?      1        22        22\n\
+              2018-09 sys-kernel/gentoo-sources:4.15.6         1          79        79      1        15        15\n\
+              2018-09 sys-kernel/gentoo-sources:4.15.7         1          75        75      0         0         ?\n\
+              2018-09 sys-kernel/gentoo-sources:4.15.7-r1      1          82        82      0         0         ?\n\
+              2018-10 sys-kernel/gentoo-sources:4.15.7         0           0         ?      1        23        23\n\
+              2018-11 sys-kernel/gentoo-sources:4.15.7-r1      0           0         ?      1        13        13\n\
+              2018-11 sys-kernel/gentoo-sources:4.15.9         1         120       120      0         0         ?\n"),
             (&["-F","test/emerge.10000.log","s","--duration","s","-sp","gentoo-sources","-gd"],
-             "2018-02-04 sys-kernel/gentoo-sources      1          81        81      0         0         ?\n\
-              2018-02-05 sys-kernel/gentoo-sources      1          95        95      0         0         ?\n\
-              2018-02-06 sys-kernel/gentoo-sources      0           0         ?      3        66        22\n\
-              2018-02-08 sys-kernel/gentoo-sources      1          97        97      0         0         ?\n\
-              2018-02-12 sys-kernel/gentoo-sources      1          80        80      0         0         ?\n\
-              2018-02-18 sys-kernel/gentoo-sources      1         118       118      0         0         ?\n\
-              2018-02-22 sys-kernel/gentoo-sources      0           0         ?      3        37        12\n\
-              2018-02-23 sys-kernel/gentoo-sources      1          77        77      0         0         ?\n\
-              2018-02-26 sys-kernel/gentoo-sources      1          79        79      0         0         ?\n\
-              2018-02-27 sys-kernel/gentoo-sources      0           0         ?      2        46        23\n\
-              2018-02-28 sys-kernel/gentoo-sources      1          75        75      0         0         ?\n\
-              2018-03-01 sys-kernel/gentoo-sources      1          82        82      1        15        15\n\
-              2018-03-05 sys-kernel/gentoo-sources      0           0         ?      1        23        23\n\
-              2018-03-12 sys-kernel/gentoo-sources      1         120       120      1        13        13\n"),
+             "2018-02-04 sys-kernel/gentoo-sources                1          81        81      0         0         ?\n\
+              2018-02-05 sys-kernel/gentoo-sources:4.15.1         1          95        95      0         0         ?\n\
+              2018-02-06 sys-kernel/gentoo-sources:?              0           0         ?      2        27        13\n\
+              2018-02-06 sys-kernel/gentoo-sources:4.14.17        0           0         ?      1        39        39\n\
+              2018-02-08 sys-kernel/gentoo-sources:4.15.2         1          97        97      0         0         ?\n\
+              2018-02-12 sys-kernel/gentoo-sources:4.15.3         1          80        80      0         0         ?\n\
+              2018-02-18 sys-kernel/gentoo-sources:4.15.4         1         118       118      0         0         ?\n\
+              2018-02-22 sys-kernel/gentoo-sources:4.15.1         0           0         ?      1        12        12\n\
+              2018-02-22 sys-kernel/gentoo-sources:4.15.2         0           0         ?      1        12        12\n\
+              2018-02-22 sys-kernel/gentoo-sources:4.15.3         0           0         ?      1        13        13\n\
+              2018-02-23 sys-kernel/gentoo-sources:4.15.5         1          77        77      0         0         ?\n\
+              2018-02-26 sys-kernel/gentoo-sources:4.15.6         1          79        79      0         0         ?\n\
+              2018-02-27 sys-kernel/gentoo-sources:4.15.4         0           0         ?      1        24        24\n\
+              2018-02-27 sys-kernel/gentoo-sources:4.15.5         0           0         ?      1        22        22\n\
+              2018-02-28 sys-kernel/gentoo-sources:4.15.7         1          75        75      0         0         ?\n\
+              2018-03-01 sys-kernel/gentoo-sources:4.15.6         0           0         ?      1        15        15\n\
+              2018-03-01 sys-kernel/gentoo-sources:4.15.7-r1      1          82        82      0         0         ?\n\
+              2018-03-05 sys-kernel/gentoo-sources:4.15.7         0           0         ?      1        23        23\n\
+              2018-03-12 sys-kernel/gentoo-sources:4.15.7-r1      0           0         ?      1        13        13\n\
+              2018-03-12 sys-kernel/gentoo-sources:4.15.9         1         120       120      0         0         ?\n"),
             (&["-F","test/emerge.10000.log","s","--duration","s","-st","-gy"],
              "2018 Total    831      216426       260    832      2311         2\n"),
             (&["-F","test/emerge.10000.log","s","--duration","s","-st","-gm"],
@@ -721,6 +2587,212 @@ mod tests {
                 tots);
     }
 
+    #[test]
+    fn stats_grouped_limit() {
+        #[rustfmt::skip]
+        let t: Vec<(&[&str], &str)> = vec![
+            (&["-F","test/emerge.10000.log","s","--duration","s","-st","-gw","--last","2","-r"],
+             "2018-11 Total     95        4380        46     95       213         2\n\
+              2018-10 Total    182       43782       240    187       519         2\n"),
+            (&["-F","test/emerge.10000.log","s","--duration","s","-st","-gd","--first","2"],
+             "2018-02-03 Total     32        2741        85     32        70         2\n\
+              2018-02-04 Total     31       30836       994     28        62         2\n"),
+        ];
+        for (a, o) in t {
+            emlop().args(a).assert().success().stdout(o);
+        }
+    }
+
+    #[test]
+    fn trend() {
+        #[rustfmt::skip]
+        let t: Vec<(&[&str],&str,i32)> = vec![
+            (&["-F","test/emerge.10000.log","t","client"],
+             "mail-client/thunderbird           2       35:46       47:58    +34.1%      +12:12/build\n\
+              www-client/chromium               3     7:56:03     7:42:07     -2.9%      -13:56/build\n\
+              kde-frameworks/kxmlrpcclient      2          31          16    -48.4%         -15/build\n",
+             0),
+            (&["-F","test/emerge.10000.log","t","--duration","s","-e","gentoo-sources"],
+             "sys-kernel/gentoo-sources     10          94          86     -8.1%          +1/build\n",
+             0),
+            (&["-F","test/emerge.10000.log","t","--duration","s","-e","gentoo-sources","--window","2"],
+             "sys-kernel/gentoo-sources     10          77         101    +31.2%         +13/build\n",
+             0),
+            (&["-F","test/emerge.10000.log","t","-e","falkon"],
+             "",
+             2),
+        ];
+        for (a, o, e) in t {
+            emlop().args(a).assert().code(e).stdout(o);
+        }
+    }
+
+    #[test]
+    fn installed() {
+        let days = |t: i64| (ts(0) - t) / 86400;
+        #[rustfmt::skip]
+        let t: Vec<(&[&str], String, i32)> = vec![
+            (&["-F","test/emerge.10000.log","i","postgres","mlt"],
+             format!("app-eselect/eselect-postgresql-2.3  2018-02-22 09:48:48         23  {:>6}\n\
+                      media-libs/mlt-6.4.1-r6             2018-02-27 16:48:40         39  {:>6}\n\
+                      dev-db/postgresql-10.3              2018-03-02 16:39:46       2:58  {:>6}\n\
+                      dev-db/postgresql-9.5.12            2018-03-02 16:43:00       3:14  {:>6}\n",
+                     days(1519292928), days(1519750120), days(1520008786), days(1520008980)),
+             0),
+            // Explicitly unmerged
+            (&["-F","test/emerge.10000.log","i","-e","pillow"],
+             String::new(),
+             2),
+        ];
+        for (a, o, e) in t {
+            emlop().args(a).assert().code(e).stdout(o);
+        }
+    }
+
+    #[test]
+    fn audit() {
+        #[rustfmt::skip]
+        let t: Vec<(&[&str], &str, i32)> = vec![
+            (&["-F","test/emerge.10000.log","--pkgdir","test/vdb","a","postgres","mlt","unlogged","kactivities"],
+             "Not in VDB        app-eselect/eselect-postgresql    2.3\n\
+              Not in log        app-misc/unlogged                 1.0\n\
+              Version mismatch  dev-db/postgresql                 log: 9.5.12 10.3, VDB: 9.5.11 10.3\n\
+              Not in VDB        kde-frameworks/kactivities-stats  5.44.0\n\
+              Not in VDB        media-libs/mlt                    6.4.1-r6\n",
+             0),
+            (&["-F","test/emerge.10000.log","--pkgdir","test/vdb","a","-e","kactivities"],
+             "",
+             2),
+            (&["-F","test/emerge.10000.log","--pkgdir","test/notfound","a"],
+             "",
+             1),
+        ];
+        for (a, o, e) in t {
+            emlop().args(a).assert().code(e).stdout(o);
+        }
+    }
+
+    #[test]
+    fn accuracy() {
+        #[rustfmt::skip]
+        let t: Vec<(&[&str], &str, i32)> = vec![
+            (&["-F","test/emerge.10000.log","ac","--worst","3"],
+             "www-client/chromium         2   1:17:39     16.4%\n\
+              app-office/libreoffice      2     26:21     17.1%\n\
+              dev-qt/qtwebengine          1     49:25     21.1%\n\
+              Total                     363      1:17     39.1%\n\
+              Unknown                   468\n",
+             0),
+            (&["-F","test/emerge.10000.log","ac","--duration","s","--predict-algo","median","plasma-desktop"],
+             "kde-plasma/plasma-desktop      4       131     30.8%\n\
+              Total                          4       131     30.8%\n\
+              Unknown                        1\n",
+             0),
+            (&["-F","test/emerge.10000.log","ac","--duration","s","--limit","1","plasma-desktop"],
+             "kde-plasma/plasma-desktop      4       216     44.5%\n\
+              Total                          4       216     44.5%\n\
+              Unknown                        1\n",
+             0),
+            (&["-F","test/emerge.10000.log","ac","icu"],
+             "Unknown      1\n",
+             2),
+        ];
+        for (a, o, e) in t {
+            emlop().args(a).assert().code(e).stdout(o);
+        }
+    }
+
+    #[test]
+    fn diff() {
+        #[rustfmt::skip]
+        let t: Vec<(&[&str], &str, i32)> = vec![
+            (&["-F","test/emerge.10000.log","-f","2018-03-02","-t","2018-03-03","diff"],
+             "Upgrade  app-portage/emlop      0.1.0_p20180221 -> 0.1.0_p20180302\n\
+              Upgrade  dev-db/postgresql:9.5  9.5.11 -> 9.5.12\n\
+              Upgrade  dev-db/postgresql:10   10.2 -> 10.3\n\
+              Upgrade  dev-util/meson         0.43.0 -> 0.44.1\n",
+             0),
+            (&["-F","test/emerge.10000.log","-f","2018-02-27 15:00","-t","2018-02-27 16:45","diff","-e","alsa-lib","unixODBC","certifi","beautifulsoup"],
+             "Upgrade  dev-db/unixODBC             2.3.4-r1 -> 2.3.5\n\
+              Rebuild  dev-python/beautifulsoup:4  4.5.1\n\
+              Removed  dev-python/beautifulsoup    3.2.1-r1\n\
+              Rebuild  dev-python/certifi          2017.7.27.1\n\
+              Rebuild  media-libs/alsa-lib         1.1.2\n",
+             0),
+            (&["-F","test/emerge.10000.log","-f","2018-03-02","-t","2018-03-03","diff","--json","postgresql","meson"],
+             "[\n\
+              \x20\x20{\"change\": \"upgrade\", \"package\": \"dev-db/postgresql\", \"slot\": \"9.5\", \"old\": \"9.5.11\", \"new\": \"9.5.12\"},\n\
+              \x20\x20{\"change\": \"upgrade\", \"package\": \"dev-db/postgresql\", \"slot\": \"10\", \"old\": \"10.2\", \"new\": \"10.3\"},\n\
+              \x20\x20{\"change\": \"upgrade\", \"package\": \"dev-util/meson\", \"slot\": \"0\", \"old\": \"0.43.0\", \"new\": \"0.44.1\"}\n\
+              ]\n",
+             0),
+            (&["-F","test/emerge.10000.log","-t","2018-02-04","diff","-e","icu"],
+             "New  dev-libs/icu  60.2\n",
+             0),
+            (&["-F","test/emerge.10000.log","-f","2018-03-02 10:00","-t","2018-03-02 16:00","diff"],
+             "",
+             2),
+        ];
+        for (a, o, e) in t {
+            emlop().args(a).assert().code(e).stdout(o);
+        }
+    }
+
+    #[test]
+    fn jobs() {
+        #[rustfmt::skip]
+        let t: Vec<(&[&str], &str, i32)> = vec![
+            (&["-F","test/emerge.10000.log","-f","2018-02-06","-t","2018-02-07","jobs"],
+             "2018-02-06 11:47:33      2     1    1.00          43          43\n\
+              2018-02-06 13:47:09      4     1    1.00        7:36        7:36\n\
+              2018-02-06 15:41:58      1     1    1.00        1:56        1:56\n\
+              2018-02-06 17:49:45      1     1    1.00          14          14\n\
+              2018-02-06 22:42:45     36     4    1.83       33:18     1:00:59\n\
+              Total                   44     4    1.63       43:47     1:11:28\n",
+             0),
+            (&["-F","test/emerge.10000.log","jobs","-g","m"],
+             "2018-02    533     4    1.11    39:45:39    43:58:32\n\
+              2018-03    298     4    1.15    14:04:52    16:08:34\n\
+              Total      831     4    1.12    53:50:31    60:07:06\n",
+             0),
+            (&["-F","test/emerge.10000.log","jobs","-g","m","--first","1"],
+             "2018-02    533     4    1.11    39:45:39    43:58:32\n\
+              Total      831     4    1.12    53:50:31    60:07:06\n",
+             0),
+            (&["-F","test/emerge.10000.log","-t","2018-02-01","jobs"],
+             "",
+             2),
+        ];
+        for (a, o, e) in t {
+            emlop().args(a).assert().code(e).stdout(o);
+        }
+    }
+
+    #[test]
+    fn gantt() {
+        #[rustfmt::skip]
+        let t: Vec<(&[&str], &str, i32)> = vec![
+            (&["-F","test/emerge.10000.log","-f","2018-02-06 22:40","-t","2018-02-06 22:55","gantt","--width","20","kscreen","breeze","oxygen"],
+             "kde-plasma/libkscreen-5.12.0           54  |##                  |\n\
+              kde-plasma/breeze-5.12.0             2:04  |        #####       |\n\
+              kde-plasma/kscreenlocker-5.12.0      1:14  |         ###        |\n\
+              kde-plasma/oxygen-5.12.0             3:23  |           #########|\n\
+              kde-plasma/kscreen-5.12.0            1:16  |                ### |\n\
+              Total                                8:20   2018-02-06 22:43:36 - 2018-02-06 22:51:56\n",
+             0),
+            (&["-F","test/emerge.10000.log","-t","2018-02-04","gantt","-e","icu"],
+             "dev-libs/icu-60.2      1:43  |############################################################|\n\
+              Total                  1:43   2018-02-03 23:11:47 - 2018-02-03 23:13:30\n",
+             0),
+            (&["-F","test/emerge.10000.log","gantt","notfound"],
+             "",
+             2),
+        ];
+        for (a, o, e) in t {
+            emlop().args(a).assert().code(e).stdout(o);
+        }
+    }
+
     /// Test behaviour when clock goes backward between merge start and merge end. Likely to happen
     /// when you're bootstrapping an Gentoo and setting the time halfway through.
     #[test]
@@ -742,7 +2814,18 @@ mod tests {
                            kde-plasma/kwin        3        9:06      4:33      2         3         1\n\
                            net-misc/chrony        1          34        34      0         0         ?\n\
                            Total                  5       10:06      2:01      2         3         1\n\
-                           Sync                   2        1:09      1:09\n")),]
+                           Sync                   2        1:09      1:09\n")),
+                 // For `jobs` the negative merge time is used for count but ignored for durations.
+                 (vec!["-F", "test/emerge.negtime.log", "j"],
+                  format!("2019-06-05 11:20:58      1     1    1.00        5:56        5:56\n\
+                           2019-06-06 02:11:22      3     1    1.00        1:00        1:00\n\
+                           2019-06-08 21:30:26      1     1    1.00        3:10        3:10\n\
+                           Total                    5     1    1.00       10:06       10:06\n")),
+                 // For `gantt` the negative merge is drawn with a minimal bar at its start.
+                 (vec!["-F", "test/emerge.negtime.log", "-f", "2019-06-05", "-t", "2019-06-07", "g", "--width", "20", "kwin"],
+                  format!("kde-plasma/kwin-5.15.5      5:56  |#                   |\n\
+                           kde-plasma/kwin-5.15.5         ?  |                   #|\n\
+                           Total                   14:49:55   2019-06-05 11:20:58 - 2019-06-06 02:10:53\n")),]
         {
             emlop().args(a).assert().success().stdout(o);
         }
@@ -758,9 +2841,18 @@ mod tests {
         let _cache_cargo_build = emlop();
         let a = vec!["-F", "test/emerge.negtime.log", "p", "--date", "unix"];
         let i = "[ebuild   R   ~] kde-plasma/kwin-5.15.5\n";
-        let o = format!("kde-plasma/kwin-5.15.5                              4:33\n\
-                           Estimate for 1 ebuilds (0 unknown, 0 elapsed)       4:33 @ {}\n",
-                        ts(4 * 60 + 33));
+        let o = format!("kde-plasma/kwin-5.15.5                              4:33  3:27 - 5:39\n\
+                           Estimate for 1 ebuilds (0 unknown, 0 elapsed)       4:33 @ {}\n\
+                           Estimate range                                      3:27 - 5:39 @ {} - {}\n",
+                        ts(4 * 60 + 33), ts(3 * 60 + 27), ts(5 * 60 + 39));
+        emlop().args(a).write_stdin(i).assert().success().stdout(o);
+        // A slot with only negative times falls back to the other slots
+        let a = vec!["-F", "test/emerge.negslot.log", "p", "--date", "unix"];
+        let i = "[ebuild   R    ] kde-plasma/kwin-5.16.0:6::gentoo  0 KiB\n";
+        let o = format!("kde-plasma/kwin-5.16.0                              5:56  5:56 - 5:56\n\
+                           Estimate for 1 ebuilds (0 unknown, 0 elapsed)       5:56 @ {}\n\
+                           Estimate range                                      5:56 - 5:56 @ {} - {}\n",
+                        ts(5 * 60 + 56), ts(5 * 60 + 56), ts(5 * 60 + 56));
         emlop().args(a).write_stdin(i).assert().success().stdout(o);
     }
 
@@ -781,15 +2873,29 @@ mod tests {
                  (&["l", "--logfile", "notfound"], 1),
                  (&["s", "--logfile", "notfound"], 1),
                  (&["p", "--logfile", "notfound"], 1),
+                 (&["i", "--logfile", "notfound"], 1),
                  (&["l", "bad regex [a-z"], 1),
                  (&["s", "bad regex [a-z"], 1),
                  (&["p", "bad regex [a-z"], 1),
+                 (&["l", ">=bad-atom"], 1),
+                 (&["l", "-x", "bad regex [a-z"], 1),
+                 (&["l", "--package-file", "notfound"], 1),
+                 (&["l", "--min-duration", "1:60"], 1),
+                 (&["l", "--first", "2", "--last", "2"], 1),
+                 (&["l", "--last", "-1"], 1),
+                 (&["s", "--last", "2"], 1),
+                 (&["s", "--max-duration", "1h"], 1),
+                 (&["s", "--details", "--versions"], 1),
+                 (&["s", "--details", "--pkgdir", "notfound"], 1),
+                 (&["-F", "test/emerge.10000.log", "t", "--window", "x"], 1),
+                 (&["l", "--setsdir", "test/portage/sets", "@notfound"], 1),
                  // Normal behaviour
                  (&["-F", "test/emerge.10000.log", "p"], 2),
                  (&["-F", "test/emerge.10000.log", "l"], 0),
                  (&["-F", "test/emerge.10000.log", "l", "-s"], 0),
                  (&["-F", "test/emerge.10000.log", "l", "-e", "icu"], 0),
                  (&["-F", "test/emerge.10000.log", "l", "-e", "unknown"], 2),
+                 (&["-F", "test/emerge.10000.log", "l", "--min-duration", "100:00:00"], 2),
                  (&["-F", "test/emerge.10000.log", "l", "--from", "2018-09-28"], 2),
                  (&["-F", "test/emerge.10000.log", "l", "-s", "--from", "2018-09-28"], 2),
                  (&["-F", "test/emerge.10000.log", "s"], 0),
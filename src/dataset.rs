@@ -0,0 +1,121 @@
+//! Portable merge times dataset, written by `emlop export` and read by `emlop import`.
+//!
+//! The format is a CSV file with a `package,slot,count,times` header and one line per package
+//! slot: total merge count, then the last merge times in seconds (oldest first, space-separated).
+//! Lines starting with `#` are ignored.
+
+use anyhow::{bail, Context, Error};
+use std::{collections::BTreeMap, fs::read_to_string, io::Write, path::Path};
+
+const HEADER: &str = "package,slot,count,times";
+
+/// Merge count and merge times (oldest first), per package and slot.
+#[derive(Default)]
+pub struct Dataset {
+    pub times: BTreeMap<(String, String), (u64, Vec<i64>)>,
+}
+impl Dataset {
+    /// Add merge times for a package slot, appending to any previous ones.
+    pub fn insert(&mut self, ebuild: &str, slot: &str, count: u64, times: &[i64]) {
+        let (c, t) = self.times.entry((ebuild.to_string(), slot.to_string())).or_default();
+        *c += count;
+        t.extend_from_slice(times);
+    }
+
+    /// Combine datasets from different machines
+    ///
+    /// Merge times of each package slot are interleaved (newest first), so that predictions, which
+    /// look at the last merge times, use all datasets whatever their order.
+    pub fn merge(datasets: Vec<Self>) -> Self {
+        let mut res = Self::default();
+        let mut all: BTreeMap<(String, String), Vec<Vec<i64>>> = BTreeMap::new();
+        for dataset in datasets {
+            for (key, (count, times)) in dataset.times {
+                res.times.entry(key.clone()).or_default().0 += count;
+                all.entry(key).or_default().push(times);
+            }
+        }
+        for (key, mut series) in all {
+            let (_, times) = res.times.entry(key).or_default();
+            while series.iter().any(|s| !s.is_empty()) {
+                times.extend(series.iter_mut().filter_map(|s| s.pop()));
+            }
+            times.reverse();
+        }
+        res
+    }
+
+    pub fn read(file: &Path) -> Result<Self, Error> {
+        let content = read_to_string(file).with_context(|| format!("Cannot open {:?}", file))?;
+        Self::parse(&content).with_context(|| format!("Cannot parse {:?}", file))
+    }
+
+    pub fn parse(content: &str) -> Result<Self, Error> {
+        let mut res = Self::default();
+        for (n, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line == HEADER {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(',').collect();
+            if fields.len() != 4 || !fields[0].contains('/') {
+                bail!("Line {}: expected '{}'", n + 1, HEADER);
+            }
+            let count =
+                fields[2].parse()
+                         .with_context(|| format!("Line {}: bad count {:?}", n + 1, fields[2]))?;
+            let times =
+                fields[3].split_whitespace()
+                         .map(|t| t.parse())
+                         .collect::<Result<Vec<i64>, _>>()
+                         .with_context(|| format!("Line {}: bad times {:?}", n + 1, fields[3]))?;
+            res.insert(fields[0], fields[1], count, &times);
+        }
+        Ok(res)
+    }
+
+    pub fn write(&self, out: &mut impl Write) -> Result<(), Error> {
+        writeln!(out, "{}", HEADER)?;
+        for ((ebuild, slot), (count, times)) in &self.times {
+            let times: Vec<String> = times.iter().map(|t| t.to_string()).collect();
+            writeln!(out, "{},{},{},{}", ebuild, slot, count, times.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let csv = "package,slot,count,times\n\
+                   # comment\n\
+                   dev-db/postgresql,10,3,100 120\n\
+                   dev-lang/rust,,1,\n\
+                   dev-db/postgresql,10,1,110\n";
+        let d = Dataset::parse(csv).unwrap();
+        assert_eq!(d.times.len(), 2);
+        assert_eq!(d.times[&("dev-db/postgresql".into(), "10".into())], (4, vec![100, 120, 110]));
+        let mut out = vec![];
+        d.write(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "package,slot,count,times\n\
+                    dev-db/postgresql,10,4,100 120 110\n\
+                    dev-lang/rust,,1,\n");
+        for bad in &["foo,1,1", "foo,1,1,1", "a/b,1,x,1", "a/b,1,1,1 x"] {
+            assert!(Dataset::parse(bad).is_err(), "{}", bad);
+        }
+        assert!(Dataset::read(Path::new("notfound")).is_err());
+    }
+
+    #[test]
+    fn merge() {
+        let a = Dataset::parse("a/b,1,3,1 2 3\nc/d,,1,5").unwrap();
+        let b = Dataset::parse("a/b,1,4,10 20").unwrap();
+        let m = Dataset::merge(vec![a, b]);
+        assert_eq!(m.times[&("a/b".into(), "1".into())], (7, vec![1, 10, 2, 20, 3]));
+        assert_eq!(m.times[&("c/d".into(), "".into())], (1, vec![5]));
+    }
+}
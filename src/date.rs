@@ -1,4 +1,4 @@
-use crate::Styles;
+use crate::{Styles, TimeShow};
 use anyhow::{bail, Error};
 use log::{debug, warn};
 use regex::Regex;
@@ -59,6 +59,19 @@ pub fn fmt_time(ts: i64, style: &Styles) -> String {
     }
 }
 
+/// Format the start and/or end time of an event, using a placeholder if the start is unknown.
+pub fn fmt_time_range(start: Option<i64>, end: i64, show: TimeShow, style: &Styles) -> String {
+    let fmt_start = || match start {
+        Some(t) => fmt_time(t, style),
+        None => format!("{:>1$}", "?", fmt_time(end, style).len()),
+    };
+    match show {
+        TimeShow::Start => fmt_start(),
+        TimeShow::End => fmt_time(end, style),
+        TimeShow::Both => format!("{} - {}", fmt_start(), fmt_time(end, style)),
+    }
+}
+
 pub fn epoch_now() -> i64 {
     SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64
 }
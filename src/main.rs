@@ -1,8 +1,11 @@
 mod cli;
 mod commands;
+mod dataset;
 mod date;
 mod parser;
 mod proces;
+mod sets;
+mod vdb;
 
 use crate::{commands::*, date::*};
 use ansi_term::{Color::*, Style};
@@ -31,6 +34,15 @@ fn main() {
         ("log", Some(sub_args)) => cmd_list(&args, sub_args, &styles),
         ("stats", Some(sub_args)) => cmd_stats(&mut tw, &args, sub_args, &styles),
         ("predict", Some(sub_args)) => cmd_predict(&mut tw, &args, sub_args, &styles),
+        ("trend", Some(sub_args)) => cmd_trend(&mut tw, &args, sub_args, &styles),
+        ("installed", Some(sub_args)) => cmd_installed(&mut tw, &args, sub_args, &styles),
+        ("audit", Some(sub_args)) => cmd_audit(&mut tw, &args, sub_args, &styles),
+        ("accuracy", Some(sub_args)) => cmd_accuracy(&mut tw, &args, sub_args, &styles),
+        ("diff", Some(sub_args)) => cmd_diff(&mut tw, &args, sub_args, &styles),
+        ("jobs", Some(sub_args)) => cmd_jobs(&mut tw, &args, sub_args, &styles),
+        ("gantt", Some(sub_args)) => cmd_gantt(&mut tw, &args, sub_args, &styles),
+        ("export", Some(sub_args)) => cmd_export(&args, sub_args, &styles),
+        ("import", Some(sub_args)) => cmd_import(&args, sub_args),
         ("complete", Some(sub_args)) => cmd_complete(sub_args),
         (other, _) => unimplemented!("{} subcommand", other),
     };
@@ -92,6 +104,19 @@ pub fn parse_limit(s: &str) -> Result<u16, String> {
                     })
 }
 
+pub fn parse_jobs(s: &str) -> Result<u16, String> {
+    match u16::from_str(s) {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("Must be an integer between 1 and {}", u16::MAX)),
+    }
+}
+
+pub fn parse_factor(s: &str) -> Result<f64, String> {
+    match f64::from_str(s) {
+        Ok(f) if f > 0.0 && f.is_finite() => Ok(f),
+        _ => Err("Must be a positive number".into()),
+    }
+}
 
 #[derive(Clone, Copy, Default)]
 pub struct Show {
@@ -112,6 +137,46 @@ impl FromStr for Show {
     }
 }
 
+/// How to predict the next merge time from previous ones, see `Times::pred()`.
+#[derive(Clone, Copy)]
+pub enum PredictAlgo {
+    Mean,
+    Median,
+    Ewma,
+    Trimmed,
+}
+impl FromStr for PredictAlgo {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mean" => Ok(PredictAlgo::Mean),
+            "median" => Ok(PredictAlgo::Median),
+            "ewma" => Ok(PredictAlgo::Ewma),
+            "trimmed" => Ok(PredictAlgo::Trimmed),
+            _ => Err("Valid values are 'mean', 'median', 'ewma', 'trimmed'.".into()),
+        }
+    }
+}
+
+/// Which timestamp(s) of an event to display.
+#[derive(Clone, Copy)]
+pub enum TimeShow {
+    Start,
+    End,
+    Both,
+}
+impl FromStr for TimeShow {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "start" => Ok(TimeShow::Start),
+            "end" => Ok(TimeShow::End),
+            "both" => Ok(TimeShow::Both),
+            _ => Err("Valid values are 'start', 'end', 'both'.".into()),
+        }
+    }
+}
+
 #[derive(Clone, Copy)]
 pub enum DurationStyle {
     HMS,
@@ -151,13 +216,33 @@ pub fn fmt_duration(style: DurationStyle, secs: i64) -> String {
     }
 }
 
+/// Parse a duration in one of the formats emitted by `fmt_duration()`.
+pub fn parse_duration(s: &str) -> Result<i64, String> {
+    let mut secs: i64 = 0;
+    let parts: Vec<&str> = s.trim().split(':').collect();
+    if parts.len() > 3 {
+        return Err("Expected [[hours:]minutes:]seconds".into());
+    }
+    for (i, p) in parts.iter().enumerate() {
+        let n = i64::from_str(p).map_err(|_| format!("Invalid number '{}'", p))?;
+        if n < 0 || (i > 0 && n > 59) {
+            return Err(format!("Out of range number '{}'", p));
+        }
+        secs = secs * 60 + n;
+    }
+    Ok(secs)
+}
+
 /// Holds styling preferences.
 ///
 /// Colors use prefix/suffix() instead of paint() because paint() doesn't handle '{:>9}' alignments
-/// properly.
+/// properly. The `*dep_p` variants are used for packages that are not in the world file.
 pub struct Styles {
     pkg_p: String,
+    pkg_s: String,
+    pkgdep_p: String,
     merge_p: String,
+    mergedep_p: String,
     merge_s: String,
     unmerge_p: String,
     unmerge_s: String,
@@ -185,7 +270,10 @@ impl Styles {
     fn new(color: bool, duration: DurationStyle, date: DateStyle, utc: bool) -> Self {
         if color {
             Styles { pkg_p: Style::new().fg(Green).bold().prefix().to_string(),
+                     pkg_s: Style::new().fg(Green).bold().suffix().to_string(),
+                     pkgdep_p: Style::new().fg(Green).prefix().to_string(),
                      merge_p: Style::new().fg(Green).bold().prefix().to_string(),
+                     mergedep_p: Style::new().fg(Green).prefix().to_string(),
                      merge_s: Style::new().fg(Green).bold().suffix().to_string(),
                      unmerge_p: Style::new().fg(Red).bold().prefix().to_string(),
                      unmerge_s: Style::new().fg(Red).bold().suffix().to_string(),
@@ -198,7 +286,10 @@ impl Styles {
                      date_fmt: date }
         } else {
             Styles { pkg_p: String::new(),
+                     pkg_s: String::new(),
+                     pkgdep_p: String::new(),
                      merge_p: String::from(">>> "),
+                     mergedep_p: String::from(">>> "),
                      merge_s: String::new(),
                      unmerge_p: String::from("<<< "),
                      unmerge_s: String::new(),
@@ -235,6 +326,18 @@ mod tests {
             assert_eq!(*hms, fmt_duration(DurationStyle::HMS, *i));
             assert_eq!(*hms_fixed, fmt_duration(DurationStyle::HMSFixed, *i));
             assert_eq!(*s, fmt_duration(DurationStyle::S, *i));
+            if *i >= 0 {
+                assert_eq!(Ok(*i), parse_duration(hms));
+                assert_eq!(Ok(*i), parse_duration(hms_fixed));
+                assert_eq!(Ok(*i), parse_duration(s));
+            }
+        }
+    }
+
+    #[test]
+    fn duration_invalid() {
+        for s in &["", "a", "1:", ":1", "1:60", "1:2:3:4", "-1", "1.5"] {
+            assert!(parse_duration(s).is_err(), "{}", s);
         }
     }
 }
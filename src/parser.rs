@@ -3,11 +3,13 @@
 //! Instantiate a `Parser` and iterate over it to retrieve the events.
 
 use crate::{date::fmt_utctime, Show};
-use anyhow::{Context, Error};
+use anyhow::{bail, Context, Error};
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use log::*;
 use regex::{Regex, RegexBuilder};
-use std::{fs::File,
+use std::{cmp::Ordering,
+          collections::HashMap,
+          fs::File,
           io::{BufRead, BufReader, Read},
           thread};
 
@@ -16,8 +18,8 @@ use std::{fs::File,
 pub enum Hist {
     /// Merge started (might never complete).
     MergeStart { ts: i64, key: String, pos1: usize, pos2: usize },
-    /// Merge completed.
-    MergeStop { ts: i64, key: String, pos1: usize, pos2: usize },
+    /// Merge completed, with the package slot if the log mentioned it.
+    MergeStop { ts: i64, key: String, pos1: usize, pos2: usize, slot: Option<String> },
     /// Unmerge started (might never complete).
     UnmergeStart { ts: i64, key: String, pos: usize },
     /// Unmerge completed.
@@ -81,6 +83,8 @@ impl Hist {
 pub struct Pretend {
     pub ebuild: String,
     pub version: String,
+    /// Only available with `emerge -v`
+    pub slot: Option<String>,
 }
 
 /// Parse emerge log into a channel of `Parsed` enums.
@@ -88,20 +92,24 @@ pub fn new_hist(filename: String,
                 min_ts: Option<i64>,
                 max_ts: Option<i64>,
                 show: Show,
-                search_str: Option<&str>,
+                search: &[String],
+                exclude: &[String],
                 search_exact: bool)
                 -> Result<Receiver<Hist>, Error> {
-    debug!("new_hist input={} min={:?} max={:?} str={:?} exact={}",
-           filename, min_ts, max_ts, search_str, search_exact);
+    debug!("new_hist input={} min={:?} max={:?} str={:?} exclude={:?} exact={}",
+           filename, min_ts, max_ts, search, exclude, search_exact);
     let reader = File::open(&filename).with_context(|| format!("Cannot open {:?}", filename))?;
     let (tx, rx): (Sender<Hist>, Receiver<Hist>) = unbounded();
     // https://docs.rs/crossbeam/0.7.1/crossbeam/thread/index.html
     let filter_ts = filter_ts_fn(min_ts, max_ts);
-    let filter_pkg = filter_pkg_fn(search_str, search_exact)?;
+    let filter_pkg = filter_pkg_fn(search, exclude, search_exact)?;
     let show_merge = show.merge || show.pkg || show.tot;
     let show_unmerge = show.unmerge || show.pkg || show.tot;
     thread::spawn(move || {
         let mut prev_t = 0;
+        // Portage logs the slot of the package being merged just before completing the merge, but
+        // with `--jobs` other merges can complete in between
+        let mut autoclean: HashMap<String, String> = HashMap::new();
         for (curline, l) in BufReader::new(reader).lines().enumerate() {
             match l {
                 Ok(ref line) => {
@@ -115,22 +123,24 @@ pub fn new_hist(filename: String,
                                   fmt_utctime(t));
                         }
                         prev_t = t;
-                        if let Some(found) = parse_start(show_merge, t, s, &filter_pkg) {
-                            tx.send(found).unwrap()
-                        } else if let Some(found) = parse_stop(show_merge, t, s, &filter_pkg) {
-                            tx.send(found).unwrap()
-                        } else if let Some(found) =
-                            parse_unmergestart(show_unmerge, t, s, &filter_pkg)
-                        {
-                            tx.send(found).unwrap()
-                        } else if let Some(found) =
-                            parse_unmergestop(show_unmerge, t, s, &filter_pkg)
-                        {
-                            tx.send(found).unwrap()
-                        } else if let Some(found) = parse_syncstart(show.sync, t, s) {
-                            tx.send(found).unwrap()
-                        } else if let Some(found) = parse_syncstop(show.sync, t, s) {
-                            tx.send(found).unwrap()
+                        if let Some((ebuild, slot)) = parse_autoclean(show_merge, s) {
+                            autoclean.insert(ebuild, slot);
+                            continue;
+                        }
+                        let mut found = parse_start(show_merge, t, s, &filter_pkg)
+                            .or_else(|| parse_stop(show_merge, t, s, &filter_pkg))
+                            .or_else(|| parse_unmergestart(show_unmerge, t, s, &filter_pkg))
+                            .or_else(|| parse_unmergestop(show_unmerge, t, s, &filter_pkg))
+                            .or_else(|| parse_syncstart(show.sync, t, s))
+                            .or_else(|| parse_syncstop(show.sync, t, s));
+                        if let Some(Hist::MergeStop { key, pos1, slot, .. }) = &mut found {
+                            *slot = autoclean.remove(&key[..*pos1 - 1]);
+                        }
+                        if let Some(found) = found {
+                            if tx.send(found).is_err() {
+                                // Receiver is gone (for example because of `--first`), stop parsing
+                                break;
+                            }
                         }
                     }
                 },
@@ -150,7 +160,7 @@ pub fn new_pretend<R: Read>(reader: R, filename: &str) -> Vec<Pretend>
 {
     debug!("new_pretend input={}", filename);
     let mut out: Vec<Pretend> = vec![];
-    let re = Regex::new("^\\[ebuild[^]]+\\] (.+?)-([0-9][0-9a-z._-]*)").unwrap();
+    let re = Regex::new("^\\[ebuild[^]]+\\] (.+?)-([0-9][0-9a-z._-]*)(?::([^/:\\s]+))?").unwrap();
     for (curline, l) in BufReader::new(reader).lines().enumerate() {
         match l {
             Ok(ref line) => {
@@ -184,42 +194,150 @@ fn filter_ts_fn(min: Option<i64>, max: Option<i64>) -> impl Fn(i64) -> bool {
     move |n| n >= mi && n <= ma
 }
 
-/// Create a closure that matches package depending on options.
-fn filter_pkg_fn(package: Option<&str>, exact: bool) -> Result<impl Fn(&str) -> bool, Error> {
-    enum FilterPkg {
-        True,
-        Eq { e: String },
-        Ends { e: String },
-        Re { r: Regex },
+/// Create a closure that matches package (and version) depending on options.
+///
+/// A package matches if it matches any of the `include` terms (or if there are none), and none of
+/// the `exclude` terms. Search strings starting with an operator are parsed as a dependency atom
+/// like `>=categ/name-1.2`, `~name-1.2`, or `=categ/name-1.2*`. Otherwise we match the ebuild name
+/// with a string or a regex. Regexes are combined into a single one, to keep matching fast.
+pub fn filter_pkg_fn(include: &[String],
+                     exclude: &[String],
+                     exact: bool)
+                     -> Result<impl Fn(&str, &str) -> bool, Error> {
+    if include.is_empty() && exclude.is_empty() {
+        info!("Package filter: None");
     }
-    let fp = match (&package, exact) {
-        (None, _) => {
-            info!("Package filter: None");
-            FilterPkg::True
-        },
-        (Some(search), true) if search.contains('/') => {
-            info!("Package filter: categ/name == {}", search);
-            FilterPkg::Eq { e: search.to_string() }
-        },
-        (Some(search), true) => {
-            info!("Package filter: name == {}", search);
-            FilterPkg::Ends { e: format!("/{}", search) }
-        },
-        (Some(search), false) => {
-            info!("Package filter: categ/name ~= {}", search);
-            FilterPkg::Re { r: RegexBuilder::new(search).case_insensitive(true).build()? }
-        },
-    };
-    Ok(move |s: &str| match &fp {
-        FilterPkg::True => true,
-        FilterPkg::Eq { e } => e == s,
-        FilterPkg::Ends { e } => s.ends_with(e),
-        FilterPkg::Re { r } => r.is_match(s),
+    let inc = FilterPkg::new_vec(include, exact, "include")?;
+    let exc = FilterPkg::new_vec(exclude, exact, "exclude")?;
+    Ok(move |s: &str, ver: &str| {
+        (inc.is_empty() || inc.iter().any(|f| f.matches(s, ver)))
+        && !exc.iter().any(|f| f.matches(s, ver))
     })
 }
 
+/// A single package filter, see `filter_pkg_fn()`.
+enum FilterPkg {
+    Eq { e: String },
+    Ends { e: String },
+    Re { r: Regex },
+    Atom { e: String, op: AtomOp, v: Version },
+}
+impl FilterPkg {
+    fn new_vec(terms: &[String], exact: bool, kind: &str) -> Result<Vec<Self>, Error> {
+        let mut res = vec![];
+        let mut re = vec![];
+        for search in terms {
+            if search.starts_with(&['<', '>', '=', '~'][..]) {
+                let (op, e, v) = parse_atom(search)?;
+                info!("Package filter ({}): atom {:?} {} {}", kind, op, e, v);
+                res.push(Self::Atom { e, op, v });
+            } else if exact && search.contains('/') {
+                info!("Package filter ({}): categ/name == {}", kind, search);
+                res.push(Self::Eq { e: search.to_string() });
+            } else if exact {
+                info!("Package filter ({}): name == {}", kind, search);
+                res.push(Self::Ends { e: format!("/{}", search) });
+            } else {
+                info!("Package filter ({}): categ/name ~= {}", kind, search);
+                // Check each regex separately, to get a precise error message
+                RegexBuilder::new(search).build()?;
+                re.push(format!("(?:{})", search));
+            }
+        }
+        if !re.is_empty() {
+            res.push(Self::Re { r: RegexBuilder::new(&re.join("|")).case_insensitive(true)
+                                                                   .build()? });
+        }
+        Ok(res)
+    }
+    fn matches(&self, s: &str, ver: &str) -> bool {
+        match self {
+            Self::Eq { e } => e == s,
+            Self::Ends { e } => s.ends_with(e),
+            Self::Re { r } => r.is_match(s),
+            Self::Atom { e, op, v } => {
+                (if e.starts_with('/') { s.ends_with(e) } else { e == s }) && op.matches(v, ver)
+            },
+        }
+    }
+}
+
+/// Version operator of a dependency atom.
+#[derive(Debug, Clone, Copy)]
+enum AtomOp {
+    Less,
+    LessEq,
+    Eq,
+    /// `=categ/name-version*`: version starts with the given string (like portage does)
+    EqGlob,
+    /// `~categ/name-version`: same version, ignoring revision
+    Tilde,
+    GreaterEq,
+    Greater,
+}
+impl AtomOp {
+    fn matches(&self, atom_ver: &Version, ver: &str) -> bool {
+        match self {
+            Self::EqGlob => ver.starts_with(&atom_ver.raw),
+            Self::Tilde => atom_ver.pms_cmp(&Version::new(ver).without_rev()) == Ordering::Equal,
+            op => {
+                let o = Version::new(ver).pms_cmp(atom_ver);
+                match op {
+                    Self::Less => o == Ordering::Less,
+                    Self::LessEq => o != Ordering::Greater,
+                    Self::Eq => o == Ordering::Equal,
+                    Self::GreaterEq => o != Ordering::Less,
+                    _ => o == Ordering::Greater,
+                }
+            },
+        }
+    }
+}
+
+/// Parse "<op>[categ/]name-version[*]" into operator, name matcher, and version
+///
+/// The name is returned with a leading `/` if it has no category, for `ends_with()` matching.
+fn parse_atom(atom: &str) -> Result<(AtomOp, String, Version), Error> {
+    let (op, rest) = if let Some(r) = atom.strip_prefix(">=") {
+        (AtomOp::GreaterEq, r)
+    } else if let Some(r) = atom.strip_prefix("<=") {
+        (AtomOp::LessEq, r)
+    } else if let Some(r) = atom.strip_prefix('>') {
+        (AtomOp::Greater, r)
+    } else if let Some(r) = atom.strip_prefix('<') {
+        (AtomOp::Less, r)
+    } else if let Some(r) = atom.strip_prefix('~') {
+        (AtomOp::Tilde, r)
+    } else if let Some(r) = atom.strip_prefix('=') {
+        match r.strip_suffix('*') {
+            Some(r) => (AtomOp::EqGlob, r),
+            None => (AtomOp::Eq, r),
+        }
+    } else {
+        bail!("Atom {:?} should start with an operator", atom)
+    };
+    if rest.contains(':') {
+        bail!("Atom {:?}: slots aren't supported, use a version", atom)
+    }
+    match split_atom(rest) {
+        Some((name, ver))
+            if !name.starts_with('/')
+               && !name.ends_with('/')
+               && name.chars().all(|c| c.is_ascii_alphanumeric() || "+_.-/".contains(c)) =>
+        {
+            let name = if name.contains('/') { name.to_string() } else { format!("/{}", name) };
+            let ver = match op {
+                AtomOp::Tilde => Version::new(ver).without_rev(),
+                _ => Version::new(ver),
+            };
+            Ok((op, name, ver))
+        },
+        _ => bail!("Atom {:?} should look like '>=category/name-version'", atom),
+    }
+}
+
 /// Split "categ/name-version" into "categ/name" and "version"
-fn split_atom(atom: &str) -> Option<(&str, &str)> {
+pub fn split_atom(atom: &str) -> Option<(&str, &str)> {
     let mut start = 0;
     loop {
         let pos = atom[start..].find('-')?;
@@ -233,6 +351,160 @@ fn split_atom(atom: &str) -> Option<(&str, &str)> {
     }
 }
 
+/// Package version, ordered according to Gentoo's [PMS] rules.
+///
+/// Versions that don't follow the PMS syntax are ordered as plain strings, after all valid ones.
+/// Versions that are equal according to PMS but spelled differently (like "1.0" and "1.00") are
+/// ordered as strings, to keep `Ord` consistent with `Eq`.
+///
+/// [PMS]: https://projects.gentoo.org/pms/latest/pms.html#x1-250003.3
+#[derive(Debug, Clone)]
+pub struct Version {
+    raw: String,
+    parsed: Option<PmsVersion>,
+}
+#[derive(Debug, Clone)]
+struct PmsVersion {
+    nums: Vec<String>,
+    letter: Option<u8>,
+    /// Suffix rank (alpha < beta < pre < rc < p) and number
+    suffixes: Vec<(u8, String)>,
+    rev: String,
+}
+impl Version {
+    pub fn new(s: &str) -> Self {
+        Self { raw: s.to_string(), parsed: PmsVersion::parse(s) }
+    }
+    /// Compare according to PMS only, so that for example "1.0" is equal to "1.00"
+    pub fn pms_cmp(&self, other: &Self) -> Ordering {
+        match (&self.parsed, &other.parsed) {
+            (Some(a), Some(b)) => a.cmp(b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => self.raw.cmp(&other.raw),
+        }
+    }
+    /// Drop the "-rN" part, if any
+    fn without_rev(mut self) -> Self {
+        if let Some(p) = &mut self.parsed {
+            p.rev = String::from("0");
+        }
+        self
+    }
+}
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.raw == other.raw
+    }
+}
+impl Eq for Version {}
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.pms_cmp(other).then_with(|| self.raw.cmp(&other.raw))
+    }
+}
+impl PmsVersion {
+    fn parse(s: &str) -> Option<Self> {
+        let (s, rev) = match s.rfind("-r") {
+            Some(p) if is_digits(&s[p + 2..]) => (&s[..p], &s[p + 2..]),
+            _ => (s, "0"),
+        };
+        let mut parts = s.split('_');
+        let mut base = parts.next()?;
+        let letter = match base.as_bytes().last() {
+            Some(c) if c.is_ascii_lowercase() => {
+                base = &base[..base.len() - 1];
+                Some(*c)
+            },
+            _ => None,
+        };
+        let nums: Vec<String> = base.split('.').map(String::from).collect();
+        if !nums.iter().all(|n| is_digits(n)) {
+            return None;
+        }
+        let mut suffixes = vec![];
+        for suf in parts {
+            let pos = suf.find(|c: char| c.is_ascii_digit()).unwrap_or(suf.len());
+            let rank = match &suf[..pos] {
+                "alpha" => 0,
+                "beta" => 1,
+                "pre" => 2,
+                "rc" => 3,
+                "p" => 5,
+                _ => return None,
+            };
+            if !suf[pos..].is_empty() && !is_digits(&suf[pos..]) {
+                return None;
+            }
+            suffixes.push((rank, suf[pos..].to_string()));
+        }
+        Some(Self { nums, letter, suffixes, rev: rev.to_string() })
+    }
+}
+impl PartialEq for PmsVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for PmsVersion {}
+impl PartialOrd for PmsVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for PmsVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Numeric components: the first one is always compared as an integer, the other ones are
+        // compared as strings (ignoring trailing zeros) if either of them has a leading zero.
+        let mut ord = cmp_int(&self.nums[0], &other.nums[0]);
+        for (a, b) in self.nums.iter().zip(other.nums.iter()).skip(1) {
+            if ord != Ordering::Equal {
+                return ord;
+            }
+            ord = if a.starts_with('0') || b.starts_with('0') {
+                a.trim_end_matches('0').cmp(b.trim_end_matches('0'))
+            } else {
+                cmp_int(a, b)
+            };
+        }
+        ord.then_with(|| self.nums.len().cmp(&other.nums.len()))
+           .then_with(|| self.letter.cmp(&other.letter))
+           .then_with(|| {
+               // Suffixes: compare pairwise, a missing suffix ranks between `_rc` and `_p`.
+               let none = (4, String::new());
+               let len = std::cmp::max(self.suffixes.len(), other.suffixes.len());
+               for i in 0..len {
+                   let (ra, na) = self.suffixes.get(i).unwrap_or(&none);
+                   let (rb, nb) = other.suffixes.get(i).unwrap_or(&none);
+                   let o = ra.cmp(rb).then_with(|| cmp_int(na, nb));
+                   if o != Ordering::Equal {
+                       return o;
+                   }
+               }
+               Ordering::Equal
+           })
+           .then_with(|| cmp_int(&self.rev, &other.rev))
+    }
+}
+fn is_digits(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|c| c.is_ascii_digit())
+}
+/// Compare two strings of digits as integers, without overflow concerns
+fn cmp_int(a: &str, b: &str) -> Ordering {
+    let (a, b) = (a.trim_start_matches('0'), b.trim_start_matches('0'));
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
 fn parse_ts(line: &str, filter_ts: impl Fn(i64) -> bool) -> Option<(i64, &str)> {
     let (ts_str, rest) = line.split_at(line.find(':')?);
     let ts = ts_str.parse::<i64>().ok()?;
@@ -244,7 +516,7 @@ fn parse_ts(line: &str, filter_ts: impl Fn(i64) -> bool) -> Option<(i64, &str)>
 fn parse_start(enabled: bool,
                ts: i64,
                line: &str,
-               filter_pkg: impl Fn(&str) -> bool)
+               filter_pkg: impl Fn(&str, &str) -> bool)
                -> Option<Hist> {
     if !enabled || !line.starts_with(">>> emer") {
         return None;
@@ -254,7 +526,7 @@ fn parse_start(enabled: bool,
     let t5 = tokens.nth(1)?;
     let t6 = tokens.next()?;
     let (ebuild, version) = split_atom(t6)?;
-    if !(filter_pkg)(ebuild) {
+    if !(filter_pkg)(ebuild, version) {
         return None;
     }
     let key = format!("{}-{}{}{}", ebuild, version, t5, &t3[1..]);
@@ -265,7 +537,7 @@ fn parse_start(enabled: bool,
 fn parse_stop(enabled: bool,
               ts: i64,
               line: &str,
-              filter_pkg: impl Fn(&str) -> bool)
+              filter_pkg: impl Fn(&str, &str) -> bool)
               -> Option<Hist> {
     if !enabled || !line.starts_with("::: comp") {
         return None;
@@ -275,18 +547,30 @@ fn parse_stop(enabled: bool,
     let t6 = tokens.nth(1)?;
     let t7 = tokens.next()?;
     let (ebuild, version) = split_atom(t7)?;
-    if !(filter_pkg)(ebuild) {
+    if !(filter_pkg)(ebuild, version) {
         return None;
     }
     let key = format!("{}-{}{}{}", ebuild, version, t6, &t4[1..]);
     let pos1 = ebuild.len() + 1;
     let pos2 = pos1 + version.len();
-    Some(Hist::MergeStop { ts, key, pos1, pos2 })
+    Some(Hist::MergeStop { ts, key, pos1, pos2, slot: None })
+}
+/// Parse ">>> AUTOCLEAN: categ/name:slot" into ebuild and slot
+fn parse_autoclean(enabled: bool, line: &str) -> Option<(String, String)> {
+    if !enabled {
+        return None;
+    }
+    let (ebuild, slot) = line.strip_prefix(">>> AUTOCLEAN: ")?.trim_end().split_once(':')?;
+    Some((ebuild.to_string(), strip_subslot(slot).to_string()))
+}
+/// Reduce "slot/subslot" to "slot"
+pub fn strip_subslot(slot: &str) -> &str {
+    slot.split('/').next().unwrap_or(slot)
 }
 fn parse_unmergestart(enabled: bool,
                       ts: i64,
                       line: &str,
-                      filter_pkg: impl Fn(&str) -> bool)
+                      filter_pkg: impl Fn(&str, &str) -> bool)
                       -> Option<Hist> {
     if !enabled || !line.starts_with("=== Unmerging...") {
         return None;
@@ -294,7 +578,7 @@ fn parse_unmergestart(enabled: bool,
     let mut tokens = line.split_ascii_whitespace();
     let t3 = tokens.nth(2)?;
     let (ebuild, version) = split_atom(&t3[1..t3.len() - 1])?;
-    if !(filter_pkg)(ebuild) {
+    if !(filter_pkg)(ebuild, version) {
         return None;
     }
     let key = format!("{}-{}", ebuild, version);
@@ -304,14 +588,14 @@ fn parse_unmergestart(enabled: bool,
 fn parse_unmergestop(enabled: bool,
                      ts: i64,
                      line: &str,
-                     filter_pkg: impl Fn(&str) -> bool)
+                     filter_pkg: impl Fn(&str, &str) -> bool)
                      -> Option<Hist> {
     if !enabled || !line.starts_with(">>> unmerge success") {
         return None;
     }
     let mut tokens = line.split_ascii_whitespace();
     let (ebuild, version) = split_atom(tokens.nth(3)?)?;
-    if !(filter_pkg)(ebuild) {
+    if !(filter_pkg)(ebuild, version) {
         return None;
     }
     let key = format!("{}-{}", ebuild, version);
@@ -334,7 +618,8 @@ fn parse_syncstop(enabled: bool, ts: i64, line: &str) -> Option<Hist> {
 fn parse_pretend(line: &str, re: &Regex) -> Option<Pretend> {
     let c = re.captures(line)?;
     Some(Pretend { ebuild: c.get(1).unwrap().as_str().to_string(),
-                   version: c.get(2).unwrap().as_str().to_string() })
+                   version: c.get(2).unwrap().as_str().to_string(),
+                   slot: c.get(3).map(|m| m.as_str().to_string()) })
 }
 
 #[cfg(test)]
@@ -369,7 +654,8 @@ mod tests {
                                    unmerge: parse_unmerge,
                                    sync: parse_sync,
                                    ..Show::default() },
-                            filter_pkg,
+                            &filter_pkg.map(String::from).into_iter().collect::<Vec<_>>(),
+                            &[],
                             exact).unwrap();
         let re_atom = Regex::new("^[a-z0-9-]+/[a-zA-Z0-9_+-]+$").unwrap();
         let re_version = Regex::new("^[0-9][0-9a-z._-]*$").unwrap();
@@ -480,6 +766,13 @@ mod tests {
                      (Some("FILE"),                       false, 7, 7, 6, 6), // case-insensitive
                      (Some("file-next"),                  true,  0, 0, 0, 0), // case-sensitive
                      (Some("File-Next"),                  true,  1, 1, 0, 0), // case-sensitive
+                     (Some(">=kde-frameworks/kactivities-5.43"), false, 2, 2, 1, 1), // atom matches newer
+                     (Some("<kactivities-5.43"),          false, 0, 0, 1, 1), // atom matches older
+                     (Some("=kactivities-5.4*"),          true,  2, 2, 2, 2), // atom matches prefix
+                     (Some("~sys-apps/util-linux-2.30.2"), false, 1, 1, 1, 1), // atom ignores revision
+                     (Some("=sys-apps/util-linux-2.30.2"), false, 0, 0, 1, 1), // atom matches exact version
+                     (Some("<=sys-apps/util-linux-2.30.2-r1"), false, 1, 1, 1, 1), // atom matches revision
+                     (Some(">util-linux-2.30.2"),         false, 1, 1, 0, 0), // atom compares revision
         ];
         for (f, e, m1, m2, u1, u2) in t {
             let c = vec![("MStart", m1), ("MStop", m2), ("UStart", u1), ("UStop", u2)];
@@ -487,6 +780,39 @@ mod tests {
         }
     }
 
+    #[test]
+    /// Filtering by multiple packages and exclusions
+    fn parse_hist_filter_multi() {
+        #[rustfmt::skip]
+        let t: Vec<(&[&str], &[&str], bool, usize)> = vec![
+            (&[],                                       &[],              false, 832),
+            (&["kactivities", "kdecore"],               &[],              false, 7),
+            (&["kactivities", "kdecore"],               &[],              true,  2),
+            (&["kactivities", "<kactivities-5.43"],     &[],              true,  2),
+            (&["kactivities"],                          &["stats"],       false, 2),
+            (&["kactivities"],                          &["kactivities"], false, 0),
+            (&[],                                       &["^virtual/", "/acct-"], false, 831),
+            (&["^virtual/"],                            &[],              false, 1),
+            (&["file", "FILE"],                         &["next"],        false, 6),
+            (&["file", "file-next"],                    &[],              true, 0),
+        ];
+        for (inc, exc, exact, count) in t {
+            let inc: Vec<String> = inc.iter().map(|s| s.to_string()).collect();
+            let exc: Vec<String> = exc.iter().map(|s| s.to_string()).collect();
+            let show = Show { merge: true, ..Show::default() };
+            let hist =
+                new_hist("test/emerge.10000.log".into(), None, None, show, &inc, &exc, exact);
+            let c = hist.unwrap().iter().filter(|h| matches!(h, Hist::MergeStop { .. })).count();
+            assert_eq!(c, count, "include={:?} exclude={:?} exact={}", inc, exc, exact);
+        }
+        // Invalid terms
+        for (inc, exc) in [(vec!["("], vec![]), (vec![], vec!["("]), (vec!["a", ">=b"], vec![])] {
+            let inc: Vec<String> = inc.iter().map(|s| s.to_string()).collect();
+            let exc: Vec<String> = exc.iter().map(|s| s.to_string()).collect();
+            assert!(filter_pkg_fn(&inc, &exc, false).is_err());
+        }
+    }
+
     #[test]
     /// Filtering by timestamp
     fn parse_hist_filter_ts() {
@@ -520,7 +846,7 @@ mod tests {
         let pretend = new_pretend(File::open(filename).unwrap(), filename);
         let mut count = 0;
         // Check that all items look valid
-        for Pretend { ebuild, version } in pretend {
+        for Pretend { ebuild, version, .. } in pretend {
             assert_eq!(ebuild, expect[count].0);
             assert_eq!(version, expect[count].1);
             count += 1;
@@ -538,6 +864,53 @@ mod tests {
         parse_pretend("test/emerge-pv.basic.out", &out);
     }
 
+    #[test]
+    fn parse_pretend_slot() {
+        let slots = |f: &str| -> Vec<Option<String>> {
+            new_pretend(File::open(f).unwrap(), f).into_iter().map(|p| p.slot).collect()
+        };
+        assert_eq!(slots("test/emerge-p.basic.out"), vec![None; 5]);
+        assert_eq!(slots("test/emerge-pv.basic.out"),
+                   vec![Some("6.4.0".into()), Some("0".into()), None, None, Some("10".into())]);
+    }
+
+    #[test]
+    fn parse_hist_slot() {
+        let hist = new_hist("test/emerge.10000.log".into(),
+                            None,
+                            None,
+                            Show { merge: true, ..Show::default() },
+                            &["dev-db/postgresql".into()],
+                            &[],
+                            true).unwrap();
+        let mut slots: Vec<String> = hist.into_iter()
+                                         .filter_map(|h| match h {
+                                             Hist::MergeStop { slot, .. } => slot,
+                                             _ => None,
+                                         })
+                                         .collect();
+        slots.dedup();
+        assert_eq!(slots, vec!["10", "9.5", "10", "9.5"]);
+        // Parallel merges complete in a different order than their AUTOCLEAN lines
+        let hist = new_hist("test/emerge.autoclean.log".into(),
+                            None,
+                            None,
+                            Show { merge: true, ..Show::default() },
+                            &[],
+                            &[],
+                            false).unwrap();
+        let slots: Vec<(String, Option<String>)> =
+            hist.into_iter()
+                .filter_map(|h| match h {
+                    Hist::MergeStop { key, pos1, slot, .. } => Some((key[..pos1 - 1].into(), slot)),
+                    _ => None,
+                })
+                .collect();
+        assert_eq!(slots,
+                   vec![("dev-qt/qtcore".into(), Some("5".into())),
+                        ("kde-plasma/kwin".into(), Some("5".into()))]);
+    }
+
     #[test]
     fn parse_pretend_blocker() {
         let out = vec![("app-admin/syslog-ng", "3.13.2"), ("dev-lang/php", "7.1.13")];
@@ -571,4 +944,104 @@ mod tests {
         assert_eq!(Some(("a-b", "2foo-4-")), split_atom("a-b-2foo-4-"));
         assert_eq!(Some(("Noël", "2-bêta")), split_atom("Noël-2-bêta"));
     }
+
+    #[test]
+    fn parse_atom_() {
+        for (a, o) in [(">=c/n-1", Some(">=c/n-1")),
+                       ("<=n-1.2_p3", Some("<=/n-1.2_p3")),
+                       (">c/n-1-r1", Some(">c/n-1-r1")),
+                       ("<c/n-foo-1", Some("<c/n-foo-1")),
+                       ("=c/n-1", Some("=c/n-1")),
+                       ("=c/n-1*", Some("=*c/n-1")),
+                       ("~c/n-1-r2", Some("~c/n-1-r2")),
+                       ("=c/n", None),
+                       ("c/n-1", None),
+                       ("==c/n-1", None),
+                       ("=c/-1", None),
+                       ("=/n-1", None),
+                       (">=", None),
+                       (">=c/n-12:12", None),
+                       ("=c/n-1:0/1", None)]
+        {
+            let res = parse_atom(a).ok().map(|(op, e, v)| {
+                                            let op = match op {
+                                                AtomOp::Less => "<",
+                                                AtomOp::LessEq => "<=",
+                                                AtomOp::Eq => "=",
+                                                AtomOp::EqGlob => "=*",
+                                                AtomOp::Tilde => "~",
+                                                AtomOp::GreaterEq => ">=",
+                                                AtomOp::Greater => ">",
+                                            };
+                                            format!("{}{}-{}", op, e, v)
+                                        });
+            assert_eq!(o.map(String::from), res, "{}", a);
+        }
+    }
+
+    #[test]
+    fn version_cmp() {
+        // Each version should be strictly smaller than the next one
+        let t = ["0",
+                 "0.0",
+                 "0.0.0",
+                 "0.09",
+                 "0.1",
+                 "0.9",
+                 "0.10",
+                 "1",
+                 "1.0",
+                 "1.00",
+                 "1.0.0",
+                 "1.0.1",
+                 "1.0.1a",
+                 "1.0.1z",
+                 "1.1_alpha",
+                 "1.1_alpha1",
+                 "1.1_alpha2",
+                 "1.1_beta",
+                 "1.1_pre3",
+                 "1.1_rc",
+                 "1.1_rc1_p1",
+                 "1.1_rc10",
+                 "1.1",
+                 "1.1-r1",
+                 "1.1-r2",
+                 "1.1-r10",
+                 "1.1_p",
+                 "1.1_p1",
+                 "1.1_p1-r1",
+                 "1.1_p2_alpha",
+                 "1.1_p2",
+                 "1.2",
+                 "1.9",
+                 "1.10",
+                 "1.11",
+                 "1.20151123",
+                 "2",
+                 "10",
+                 "99999999999999999999999",
+                 "1.1_foo",
+                 "1.a",
+                 "9_x",
+                 "a1"];
+        for w in t.windows(2) {
+            let (a, b) = (Version::new(w[0]), Version::new(w[1]));
+            assert!(a < b, "{} < {}", a, b);
+            assert!(b > a, "{} > {}", b, a);
+        }
+        for v in t {
+            assert_eq!(Version::new(v), Version::new(v));
+        }
+        // Equal according to PMS, but ordered by string
+        assert!(PmsVersion::parse("1.0") == PmsVersion::parse("1.00"));
+        assert!(PmsVersion::parse("1.0") == PmsVersion::parse("1.0-r0"));
+        assert!(PmsVersion::parse("1.1_rc") == PmsVersion::parse("1.1_rc0"));
+        // Invalid versions
+        for v in
+            ["", "a", "1.", ".1", "1..2", "1-r", "1_", "1_rc_", "1ab", "1-r1a", "2-3", "2foo-4"]
+        {
+            assert!(PmsVersion::parse(v).is_none(), "{} should be invalid", v);
+        }
+    }
 }
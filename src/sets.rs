@@ -0,0 +1,151 @@
+//! Portage package sets: `@world`, `@system`, and custom sets from `/etc/portage/sets`.
+//!
+//! Sets are expanded into package search terms, see `commands::pkg_terms()`. We only support the
+//! common on-disk formats, not the full `sets.conf` machinery.
+
+use anyhow::{bail, Context, Error};
+use clap::ArgMatches;
+use log::*;
+use std::{collections::HashSet,
+          fs::read_to_string,
+          path::{Path, PathBuf}};
+
+/// Location of the files describing package sets.
+pub struct Sets {
+    world: PathBuf,
+    setsdir: PathBuf,
+    profile: PathBuf,
+}
+impl Sets {
+    pub fn from_args(args: &ArgMatches) -> Self {
+        Self { world: args.value_of("worldfile").unwrap().into(),
+               setsdir: args.value_of("setsdir").unwrap().into(),
+               profile: args.value_of("profile").unwrap().into() }
+    }
+
+    /// Expand a set name (without the leading `@`) into a list of atoms.
+    ///
+    /// Atoms have their slot and repository stripped, and are either a plain `categ/name` or
+    /// start with a version operator.
+    pub fn expand(&self, name: &str) -> Result<Vec<String>, Error> {
+        self.expand_rec(name, &mut vec![])
+    }
+
+    fn expand_rec(&self, name: &str, seen: &mut Vec<String>) -> Result<Vec<String>, Error> {
+        if seen.iter().any(|s| s == name) {
+            bail!("Set @{} includes itself", name);
+        }
+        seen.push(name.to_string());
+        let lines = match name {
+            "world" | "selected" => read_lines(&self.world)?,
+            "system" => {
+                let mut res = vec![];
+                read_profile(&self.profile, &mut res, 0)?;
+                res
+            },
+            _ => {
+                let file = self.setsdir.join(name);
+                if !file.is_file() {
+                    bail!("Unknown set @{} (no such file {:?})", name, file);
+                }
+                read_lines(&file)?
+            },
+        };
+        let mut res = vec![];
+        for l in lines {
+            match l.strip_prefix('@') {
+                Some(sub) => res.extend(self.expand_rec(sub, seen)?),
+                None => res.push(strip_slot(&l).to_string()),
+            }
+        }
+        seen.pop();
+        info!("Set @{}: {} atoms", name, res.len());
+        Ok(res)
+    }
+
+    /// Return the `categ/name` of packages in the world file, or None if it can't be read.
+    pub fn world(&self) -> Option<HashSet<String>> {
+        match read_lines(&self.world) {
+            Ok(lines) => Some(lines.iter().map(|l| strip_slot(l).to_string()).collect()),
+            Err(e) => {
+                info!("Not highlighting world packages: {:#}", e);
+                None
+            },
+        }
+    }
+}
+
+/// Read non-empty, non-comment lines from a file.
+fn read_lines(file: &Path) -> Result<Vec<String>, Error> {
+    let content = read_to_string(file).with_context(|| format!("Cannot open {:?}", file))?;
+    Ok(content.lines()
+              .map(|l| l.split('#').next().unwrap_or("").trim())
+              .filter(|l| !l.is_empty())
+              .map(String::from)
+              .collect())
+}
+
+/// Strip `:slot` and `::repo` suffixes from an atom.
+fn strip_slot(atom: &str) -> &str {
+    atom.split(':').next().unwrap_or(atom)
+}
+
+/// Collect the `@system` atoms from a profile and its parents.
+///
+/// Parents are read first, so that a `-*atom` line can remove an atom added by a parent.
+fn read_profile(dir: &Path, res: &mut Vec<String>, depth: u8) -> Result<(), Error> {
+    if depth > 32 {
+        bail!("Profile {:?} is nested too deep", dir);
+    }
+    if !dir.is_dir() {
+        bail!("Cannot open profile {:?}", dir);
+    }
+    if let Ok(parents) = read_lines(&dir.join("parent")) {
+        for p in parents {
+            read_profile(&dir.join(p), res, depth + 1)?;
+        }
+    }
+    if let Ok(packages) = read_lines(&dir.join("packages")) {
+        for l in packages {
+            if let Some(atom) = l.strip_prefix("-*") {
+                res.retain(|a| a != atom);
+            } else if let Some(atom) = l.strip_prefix('*') {
+                res.push(atom.to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sets() -> Sets {
+        Sets { world: "test/portage/world".into(),
+               setsdir: "test/portage/sets".into(),
+               profile: "test/portage/profile".into() }
+    }
+
+    #[test]
+    fn expand() {
+        let s = sets();
+        assert_eq!(s.expand("world").unwrap(),
+                   vec!["www-client/firefox", "dev-lang/rust", "kde-frameworks/kactivities"]);
+        assert_eq!(s.expand("system").unwrap(),
+                   vec!["sys-apps/baselayout", ">=sys-devel/gcc-7", "sys-apps/portage"]);
+        assert_eq!(s.expand("browsers").unwrap(),
+                   vec!["www-client/firefox", "www-client/chromium", "<www-client/links-2.15"]);
+        assert_eq!(s.expand("nested").unwrap(),
+                   vec!["app-editors/vim",
+                        "www-client/firefox",
+                        "www-client/chromium",
+                        "<www-client/links-2.15"]);
+        assert!(s.expand("notfound").is_err());
+        assert!(s.expand("loop").is_err());
+        let bad = Sets { world: "notfound".into(), setsdir: "notfound".into(), ..sets() };
+        assert!(bad.expand("world").is_err());
+        assert!(bad.world().is_none());
+        assert_eq!(s.world().unwrap().len(), 3);
+    }
+}
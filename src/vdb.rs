@@ -0,0 +1,123 @@
+//! Reads the installed package database (VDB), usually in `/var/db/pkg`.
+//!
+//! Each installed package is a `categ/name-version` directory containing one file per metadata
+//! field (`SLOT`, `USE`, `BUILD_TIME`...).
+
+use crate::parser::{split_atom, strip_subslot, Version};
+use anyhow::{Context, Error};
+use log::*;
+use std::{collections::HashMap,
+          fs::{read_dir, read_to_string},
+          path::{Path, PathBuf}};
+
+/// An installed package.
+pub struct VdbPkg {
+    pub ebuild: String,
+    pub version: String,
+    path: PathBuf,
+}
+impl VdbPkg {
+    /// Read a metadata field of this package, or None if it isn't available.
+    pub fn field(&self, name: &str) -> Option<String> {
+        read_to_string(self.path.join(name)).ok().map(|s| s.trim().to_string())
+    }
+}
+
+/// List installed packages matching `filter_pkg`, sorted by name and version (using Gentoo rules).
+pub fn read_vdb(dir: &Path, filter_pkg: impl Fn(&str, &str) -> bool) -> Result<Vec<VdbPkg>, Error> {
+    let mut res = vec![];
+    for categ in read_dir(dir).with_context(|| format!("Cannot open {:?}", dir))? {
+        let categ = categ?;
+        let categ_name = categ.file_name().to_string_lossy().to_string();
+        if categ_name.starts_with('.') || !categ.path().is_dir() {
+            continue;
+        }
+        for pkg in read_dir(categ.path())? {
+            let pkg = pkg?;
+            let pkg_name = pkg.file_name().to_string_lossy().to_string();
+            // Skip hidden files and in-progress merges
+            if pkg_name.starts_with('.') || pkg_name.starts_with("-MERGING-") {
+                continue;
+            }
+            let atom = format!("{}/{}", categ_name, pkg_name);
+            match split_atom(&atom) {
+                Some((ebuild, version)) if filter_pkg(ebuild, version) => {
+                    res.push(VdbPkg { ebuild: ebuild.to_string(),
+                                      version: version.to_string(),
+                                      path: pkg.path() })
+                },
+                Some(_) => (),
+                None => warn!("{:?}: can't parse package name", pkg.path()),
+            }
+        }
+    }
+    res.sort_by(|a, b| {
+           a.ebuild
+            .cmp(&b.ebuild)
+            .then_with(|| Version::new(&a.version).cmp(&Version::new(&b.version)))
+       });
+    debug!("Found {} packages in {:?}", res.len(), dir);
+    Ok(res)
+}
+
+/// Slot of installed packages, for merges that the log doesn't give a slot for.
+///
+/// The VDB is only read when first needed, as most merges have their slot logged.
+pub struct VdbSlots {
+    dir: PathBuf,
+    slots: Option<HashMap<String, String>>,
+}
+impl VdbSlots {
+    pub fn new(dir: &str) -> Self {
+        Self { dir: dir.into(), slots: None }
+    }
+    /// Slot of the installed `ebuild-version`, without the subslot
+    pub fn get(&mut self, ebuild: &str, version: &str) -> Option<String> {
+        let dir = &self.dir;
+        let slots = self.slots.get_or_insert_with(|| match read_vdb(dir, |_, _| true) {
+                                  Ok(vdb) => vdb.iter()
+                                                .filter_map(|p| {
+                                                    let slot = p.field("SLOT")?;
+                                                    Some((format!("{}-{}", p.ebuild, p.version),
+                                                          strip_subslot(&slot).to_string()))
+                                                })
+                                                .collect(),
+                                  Err(e) => {
+                                      debug!("No slots from VDB: {:#}", e);
+                                      HashMap::new()
+                                  },
+                              });
+        slots.get(&format!("{}-{}", ebuild, version)).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read() {
+        let all = read_vdb(Path::new("test/vdb"), |_, _| true).unwrap();
+        let names: Vec<String> =
+            all.iter().map(|p| format!("{}-{}", p.ebuild, p.version)).collect();
+        assert_eq!(names,
+                   vec!["app-misc/unlogged-1.0",
+                        "dev-db/postgresql-9.5.11",
+                        "dev-db/postgresql-10.3",
+                        "kde-frameworks/kactivities-5.44.0"]);
+        assert_eq!(all[2].field("SLOT").as_deref(), Some("10"));
+        assert_eq!(all[1].field("NOTAFIELD"), None);
+        let some = read_vdb(Path::new("test/vdb"), |e, _| e.starts_with("dev-db/")).unwrap();
+        assert_eq!(some.len(), 2);
+        assert!(read_vdb(Path::new("test/notfound"), |_, _| true).is_err());
+    }
+
+    #[test]
+    fn slots() {
+        let mut slots = VdbSlots::new("test/vdb");
+        assert_eq!(slots.get("dev-db/postgresql", "9.5.11").as_deref(), Some("9.5"));
+        assert_eq!(slots.get("kde-frameworks/kactivities", "5.44.0").as_deref(), Some("5"));
+        assert_eq!(slots.get("dev-db/postgresql", "10.4"), None);
+        assert_eq!(VdbSlots::new("test/notfound").get("dev-db/postgresql", "10.3"), None);
+    }
+}